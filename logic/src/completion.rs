@@ -0,0 +1,212 @@
+//! Knuth–Bendix completion of equations into a convergent rewrite system.
+//!
+//! Equations are oriented into rules by the lexicographic path ordering (LPO) for a given
+//! precedence, and the critical pairs of the rules are added back as equations until all of them
+//! are joinable. Completion fails on an equation whose sides the LPO cannot order, and gives up
+//! once more than the rule limit, `MAX_RULES` by default, have been created.
+
+use tree::v3::{Tree, Subtree};
+use parser::parser::{unify_tree, substitute_tree};
+use parser::sym::SymDB;
+use crate::rewrite::{self, RewriteRule, Strategy};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Default limit of rules `complete` may create.
+pub const MAX_RULES: usize = 200;
+
+fn is_variable(value: &str) -> bool {
+    value.starts_with('@')
+}
+
+fn occurs(term: Subtree<String>, var: &str) -> bool {
+    term.get_root() == var || term.children_root().any(|arg| occurs(arg, var))
+}
+
+fn size(term: &Tree<String>) -> usize {
+    term.ids().count()
+}
+
+/// The lexicographic path ordering for the precedence `precedence`, listed from the greatest symbol.
+/// Symbols not listed are smaller than the listed ones and are ordered by arity, then by name.
+pub struct Lpo {
+    precedence: Vec<String>,
+}
+
+impl Lpo {
+    pub fn new(precedence: &[String]) -> Self {
+        Self { precedence: precedence.to_vec() }
+    }
+
+    fn compare_symbols(&self, lhs: Subtree<String>, rhs: Subtree<String>) -> Ordering {
+        let rank = |term: Subtree<String>| {
+            let value = term.get_root();
+            let index = self.precedence.iter().position(|symbol| symbol == value);
+            (index.map(|i| self.precedence.len() - i), term.children_len(), value.clone())
+        };
+        rank(lhs).cmp(&rank(rhs))
+    }
+
+    /// Whether `lhs` is greater than `rhs`.
+    pub fn greater(&self, lhs: Subtree<String>, rhs: Subtree<String>) -> bool {
+        if is_variable(lhs.get_root()) {
+            return false;
+        }
+        if is_variable(rhs.get_root()) {
+            return occurs(lhs, rhs.get_root());
+        }
+        if lhs.children_root().any(|arg| arg == rhs || self.greater(arg, rhs)) {
+            return true;
+        }
+        match self.compare_symbols(lhs, rhs) {
+            Ordering::Greater => rhs.children_root().all(|arg| self.greater(lhs, arg)),
+            Ordering::Equal => {
+                if !rhs.children_root().all(|arg| self.greater(lhs, arg)) {
+                    return false;
+                }
+                std::iter::zip(lhs.children_root(), rhs.children_root())
+                    .find(|(arg_lhs, arg_rhs)| arg_lhs != arg_rhs)
+                    .is_some_and(|(arg_lhs, arg_rhs)| self.greater(arg_lhs, arg_rhs))
+            }
+            Ordering::Less => false,
+        }
+    }
+}
+
+/// Renames the variables of `lhs => rhs` to `@x`, `@y`, `@z`, `@u`, `@v`, `@w`, `@x1`, ... in order of appearance.
+fn canonical(lhs: &Tree<String>, rhs: &Tree<String>) -> (Tree<String>, Tree<String>) {
+    const NAMES: [&str; 6] = ["x", "y", "z", "u", "v", "w"];
+    let mut rename: HashMap<String, Tree<String>> = HashMap::new();
+    for term in [lhs, rhs] {
+        for id in term.ids() {
+            let value = term.get(id).unwrap();
+            if is_variable(value) && !rename.contains_key(value) {
+                let n = rename.len();
+                let name = match n / NAMES.len() {
+                    0 => format!("@{}", NAMES[n]),
+                    k => format!("@{}{}", NAMES[n % NAMES.len()], k),
+                };
+                rename.insert(value.clone(), Tree::new(name));
+            }
+        }
+    }
+    (substitute_tree(lhs.subtree_root(), &rename), substitute_tree(rhs.subtree_root(), &rename))
+}
+
+/// The critical pairs of `rule1` overlapping `rule2` at non-variable positions of the left side of `rule1`.
+fn critical_pairs(rule1: &RewriteRule, rule2: &RewriteRule, same: bool) -> Vec<(Tree<String>, Tree<String>)> {
+    let rename: HashMap<String, Tree<String>> = rule2.lhs.ids()
+        .map(|id| rule2.lhs.get(id).unwrap())
+        .filter(|value| is_variable(value))
+        .map(|value| (value.clone(), Tree::new(format!("{}'", value))))
+        .collect();
+    let lhs2 = substitute_tree(rule2.lhs.subtree_root(), &rename);
+    let rhs2 = substitute_tree(rule2.rhs.subtree_root(), &rename);
+    let mut pairs = Vec::new();
+    for id in rule1.lhs.ids() {
+        if is_variable(rule1.lhs.get(id).unwrap()) || (same && id == rule1.lhs.root_id()) {
+            continue;
+        }
+        let Some(mgu) = unify_tree(rule1.lhs.subtree(id), lhs2.subtree_root(), is_variable) else {
+            continue;
+        };
+        let mut overlap = rule1.lhs.clone();
+        overlap.paste(id, rhs2.subtree_root());
+        pairs.push((
+            substitute_tree(rule1.rhs.subtree_root(), &mgu),
+            substitute_tree(overlap.subtree_root(), &mgu),
+        ));
+    }
+    pairs
+}
+
+/// Turns the equations `lhs = rhs` into a confluent and terminating rewrite system
+/// whose rules are oriented by `lpo`, by Knuth–Bendix completion.
+/// Fails if an equation cannot be oriented or more than `max_rules` rules are created.
+pub fn complete(equations: &[(Tree<String>, Tree<String>)], lpo: &Lpo, symdb: &SymDB, max_rules: usize)
+-> Result<Vec<RewriteRule>, String> {
+    let mut equations = equations.to_vec();
+    let mut rules: Vec<RewriteRule> = Vec::new();
+    let mut created = 0;
+    while !equations.is_empty() {
+        let (i, _) = equations.iter().enumerate()
+            .min_by_key(|(_, (lhs, rhs))| size(lhs) + size(rhs))
+            .unwrap();
+        let (lhs, rhs) = equations.swap_remove(i);
+        let lhs = rewrite::normalize(&lhs, &rules, Strategy::Innermost, rewrite::MAX_STEPS).last().clone();
+        let rhs = rewrite::normalize(&rhs, &rules, Strategy::Innermost, rewrite::MAX_STEPS).last().clone();
+        if lhs == rhs {
+            continue;
+        }
+        let (lhs, rhs) = if lpo.greater(lhs.subtree_root(), rhs.subtree_root()) {
+            (lhs, rhs)
+        } else if lpo.greater(rhs.subtree_root(), lhs.subtree_root()) {
+            (rhs, lhs)
+        } else {
+            let (lhs, rhs) = canonical(&lhs, &rhs);
+            return Err(format!("cannot orient {} = {}",
+                symdb.ast_to_string_minimal(&lhs), symdb.ast_to_string_minimal(&rhs)));
+        };
+        created += 1;
+        if created > max_rules {
+            return Err(format!("no convergent system within {} rules", max_rules));
+        }
+        let (lhs, rhs) = canonical(&lhs, &rhs);
+        let rule = RewriteRule { lhs, rhs };
+        // Rules whose left side the new rule reduces go back to the equations.
+        let new_rule = std::slice::from_ref(&rule);
+        let mut kept = Vec::with_capacity(rules.len() + 1);
+        for old in rules {
+            if rewrite::step(old.lhs.subtree_root(), new_rule, Strategy::Innermost).is_some() {
+                equations.push((old.lhs, old.rhs));
+            } else {
+                kept.push(old);
+            }
+        }
+        kept.push(rule);
+        rules = kept;
+        let n = rules.len();
+        for i in 0..n {
+            let rhs = rewrite::normalize(&rules[i].rhs, &rules, Strategy::Innermost, rewrite::MAX_STEPS).last().clone();
+            rules[i].rhs = rhs;
+        }
+        let rule = &rules[n - 1];
+        for other in rules.iter() {
+            let same = std::ptr::eq(other, rule);
+            equations.extend(critical_pairs(rule, other, same));
+            if !same {
+                equations.extend(critical_pairs(other, rule, false));
+            }
+        }
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    #[test]
+    fn groups() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let equations: Vec<(Tree<String>, Tree<String>)> = ["0 + @x = @x", "- @x + @x = 0", "(@x + @y) + @z = @x + (@y + @z)"]
+            .iter()
+            .map(|s| {
+                let (lhs, rhs) = s.split_once('=').unwrap();
+                (parser.parse(lhs).unwrap(), parser.parse(rhs).unwrap())
+            })
+            .collect();
+        let lpo = Lpo::new(&["-".to_string(), "+".to_string(), "0".to_string()]);
+        let rules = complete(&equations, &lpo, parser.symdb(), MAX_RULES).unwrap();
+        assert_eq!(rules.len(), 10);
+        for (lhs, rhs) in [("- (a + - b)", "b + - a"), ("a + (- a + b) + - b", "0"), ("- - (- 0 + a)", "a")] {
+            let lhs = parser.parse(lhs).unwrap();
+            let trace = rewrite::normalize(&lhs, &rules, Strategy::Innermost, rewrite::MAX_STEPS);
+            assert_eq!(trace.last(), &parser.parse(rhs).unwrap());
+        }
+        let commutative = parser.parse("@x + @y").unwrap();
+        let swapped = parser.parse("@y + @x").unwrap();
+        assert!(complete(&[(commutative, swapped)], &lpo, parser.symdb(), MAX_RULES).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Conversion between DIMACS CNF files and formulas built from `&`, `|` and `!`.
+//!
+//! Variable names are kept in comment lines of the form `c var [number] [name]`.
+//! Variables without such a line are named `x[number]`, followed by `_[i]` if the name is already taken.
+
+use tree::v3::{Tree, Subtree};
+use parser::sym::SymDB;
+use crate::sat::{self, Cnf};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+
+pub fn write<W: Write>(cnf: &Cnf, names: &[String], mut writer: W) -> std::io::Result<()> {
+    for (i, name) in names.iter().enumerate() {
+        writeln!(writer, "c var {} {}", i + 1, name)?;
+    }
+    writeln!(writer, "p cnf {} {}", cnf.num_vars, cnf.clauses.len())?;
+    for clause in cnf.clauses.iter() {
+        for lit in clause {
+            write!(writer, "{} ", lit)?;
+        }
+        writeln!(writer, "0")?;
+    }
+    writer.flush()
+}
+
+/// Reads a DIMACS CNF file and returns it with the names of all its variables.
+/// Unnamed variables get fresh names distinct from the named ones, e.g. Tseitin auxiliary variables.
+pub fn read<R: BufRead>(reader: R) -> Result<(Cnf, Vec<String>), String> {
+    let mut header = None;
+    let mut named = HashMap::new();
+    let mut clauses = Vec::new();
+    let mut clause = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            None => continue,
+            Some("c") => {
+                if let (Some("var"), Some(var), Some(name)) = (tokens.next(), tokens.next(), tokens.next()) {
+                    let var: usize = var.parse().map_err(|_| format!("invalid variable: {}", var))?;
+                    if named.values().any(|named| named == name) {
+                        return Err(format!("duplicate variable name: {}", name));
+                    }
+                    named.insert(var, name.to_string());
+                }
+                continue;
+            }
+            Some("%") => break,
+            Some("p") => {
+                if header.is_some() {
+                    return Err("duplicate problem line".to_string());
+                }
+                let (Some("cnf"), Some(num_vars), Some(num_clauses)) = (tokens.next(), tokens.next(), tokens.next()) else {
+                    return Err(format!("invalid problem line: {}", line));
+                };
+                let num_vars: usize = num_vars.parse().map_err(|_| format!("invalid problem line: {}", line))?;
+                let num_clauses: usize = num_clauses.parse().map_err(|_| format!("invalid problem line: {}", line))?;
+                header = Some((num_vars, num_clauses));
+                continue;
+            }
+            Some(_) => {}
+        }
+        let Some((num_vars, _)) = header else {
+            return Err("missing problem line".to_string());
+        };
+        for token in line.split_whitespace() {
+            let lit: i32 = token.parse().map_err(|_| format!("invalid literal: {}", token))?;
+            if lit == 0 {
+                clauses.push(std::mem::take(&mut clause));
+            } else if lit.unsigned_abs() as usize > num_vars {
+                return Err(format!("variable out of range: {}", lit));
+            } else {
+                clause.push(lit);
+            }
+        }
+    }
+    let Some((num_vars, num_clauses)) = header else {
+        return Err("missing problem line".to_string());
+    };
+    if !clause.is_empty() {
+        clauses.push(clause);
+    }
+    if clauses.len() != num_clauses {
+        return Err(format!("expected {} clauses, found {}", num_clauses, clauses.len()));
+    }
+    let mut taken: HashSet<String> = named.values().cloned().collect();
+    let names = (1..=num_vars)
+        .map(|var| named.remove(&var).unwrap_or_else(|| {
+            let name = (0..)
+                .map(|i| if i == 0 { format!("x{}", var) } else { format!("x{}_{}", var, i) })
+                .find(|name| !taken.contains(name))
+                .unwrap();
+            taken.insert(name.clone());
+            name
+        }))
+        .collect();
+    Ok((Cnf { num_vars, clauses }, names))
+}
+
+fn join(op: &str, operands: impl Iterator<Item = Tree<String>>, empty: &str) -> Tree<String> {
+    operands
+        .reduce(|lhs, rhs| {
+            let mut tree = Tree::new(op.to_string());
+            tree.push_tree(tree.root_id(), lhs);
+            tree.push_tree(tree.root_id(), rhs);
+            tree
+        })
+        .unwrap_or_else(|| Tree::new(empty.to_string()))
+}
+
+/// Builds the conjunction of the clauses of `cnf`. An empty clause becomes `F`.
+pub fn cnf_to_ast(cnf: &Cnf, names: &[String]) -> Tree<String> {
+    let literal = |lit: i32| {
+        let var = Tree::new(names[lit.unsigned_abs() as usize - 1].clone());
+        if lit > 0 {
+            return var;
+        }
+        let mut neg = Tree::new("!".to_string());
+        neg.push_tree(neg.root_id(), var);
+        neg
+    };
+    let clauses = cnf.clauses.iter()
+        .map(|clause| join("|", clause.iter().map(|&lit| literal(lit)), "F"));
+    join("&", clauses, "T")
+}
+
+/// Collects the clauses of `form` if it is a conjunction of disjunctions of literals.
+fn collect_clauses(form: Subtree<'_, String>, symdb: &SymDB, vars: &mut Vec<String>, clauses: &mut Vec<Vec<i32>>) -> bool {
+    if form.get_root() == "&" {
+        return form.children_root().all(|arg| collect_clauses(arg, symdb, vars, clauses));
+    }
+    let mut clause = Vec::new();
+    if !collect_literals(form, symdb, vars, &mut clause) {
+        return false;
+    }
+    clauses.push(clause);
+    true
+}
+
+fn collect_literals(form: Subtree<'_, String>, symdb: &SymDB, vars: &mut Vec<String>, clause: &mut Vec<i32>) -> bool {
+    let (atom, sign) = match form.get_root().as_str() {
+        "|" => return form.children_root().all(|arg| collect_literals(arg, symdb, vars, clause)),
+        "F" if form.is_leaf() => return true,
+        "!" => (form.children_root().next().unwrap(), -1),
+        _ => (form, 1),
+    };
+    let name = atom.get_root();
+    if !atom.is_leaf() || !symdb.is_variable(name) {
+        return false;
+    }
+    let var = match vars.iter().position(|var| var == name) {
+        Some(i) => i + 1,
+        None => {
+            vars.push(name.clone());
+            vars.len()
+        }
+    };
+    clause.push(sign * var as i32);
+    true
+}
+
+/// Converts `form` to `Cnf`.
+/// A conjunction of disjunctions of literals is converted clause by clause;
+/// any other formula is converted by the Tseitin encoding, which adds auxiliary variables.
+pub fn ast_to_cnf(form: Subtree<'_, String>, symdb: &SymDB) -> Result<(Cnf, Vec<String>), String> {
+    let mut vars = Vec::new();
+    let mut clauses = Vec::new();
+    if form.get_root() == "T" && form.is_leaf() {
+        return Ok((Cnf::default(), vars));
+    }
+    if collect_clauses(form, symdb, &mut vars, &mut clauses) {
+        return Ok((Cnf { num_vars: vars.len(), clauses }, vars));
+    }
+    sat::tseitin(form, symdb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    #[test]
+    fn round_trip() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let form = parser.parse("(a | !b | c) & !a & (b | F)").unwrap();
+        let (cnf, names) = ast_to_cnf(form.subtree_root(), parser.symdb()).unwrap();
+        assert_eq!(cnf.clauses, vec![vec![1, -2, 3], vec![-1], vec![2]]);
+        let mut buf = Vec::new();
+        write(&cnf, &names, &mut buf).unwrap();
+        let (cnf_read, names_read) = read(buf.as_slice()).unwrap();
+        assert_eq!((&cnf_read, &names_read), (&cnf, &names));
+        let expected = parser.parse("(a | !b | c) & !a & b").unwrap();
+        assert_eq!(cnf_to_ast(&cnf_read, &names_read), expected);
+
+        let dimacs = "c example\np cnf 3 2\n1 -3 0 2\n3 0\n";
+        let (cnf, names) = read(dimacs.as_bytes()).unwrap();
+        assert_eq!(cnf.clauses, vec![vec![1, -3], vec![2, 3]]);
+        assert_eq!(names, ["x1", "x2", "x3"]);
+        assert!(read("p cnf 1 1\n2 0\n".as_bytes()).is_err());
+
+        let form = parser.parse("x3 -> x3 & a").unwrap();
+        let (cnf, names) = ast_to_cnf(form.subtree_root(), parser.symdb()).unwrap();
+        let mut buf = Vec::new();
+        write(&cnf, &names, &mut buf).unwrap();
+        let (_, names_read) = read(buf.as_slice()).unwrap();
+        assert_eq!(names_read, ["x3", "a", "x3_1", "x4"]);
+        assert!(read("c var 1 a\nc var 2 a\np cnf 2 0\n".as_bytes()).is_err());
+    }
+}
@@ -1,14 +1,339 @@
 use tree::v3::{Tree, Subtree};
 use parser::parser::Parser;
-use crate::util::SplitWhitespace;
-use std::collections::HashSet;
+use parser::sym::SymDB;
+use crate::{completion, latex, lk, resolution, rewrite, tptp, util};
+use crate::util::{History, SplitWhitespace, parse_subst, subst_to_string};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write, BufWriter};
 use std::io::{Error, ErrorKind};
 
+/// Returns the variables occurring free in `form`.
+pub fn free_variables(form: Subtree<'_, String>, symdb: &SymDB) -> HashSet<String> {
+    let mut fvs = HashSet::new();
+    let mut except = HashSet::new();
+    free_variables_sub(form, symdb, &mut fvs, &mut except);
+    fvs
+}
+
+fn free_variables_sub(form: Subtree<String>, symdb: &SymDB, fvs: &mut HashSet<String>, except: &mut HashSet<String>) {
+    if form.is_leaf() {
+        let var = form.get_root();
+        if !except.contains(var) {
+            if symdb.is_variable(var) {
+                fvs.insert(var.clone());
+            }
+        }
+        return;
+    }
+    let value = form.get_root();
+    let mut args = form.children_root();
+    if value == "∀" || value == "∃" {
+        let binder = args.next().unwrap().get_root().clone();
+        let inserted = except.insert(binder.clone());
+        free_variables_sub(args.next().unwrap(), symdb, fvs, except);
+        if inserted {
+            except.remove(&binder);
+        }
+        return;
+    }
+    for arg in args {
+        free_variables_sub(arg, symdb, fvs, except);
+    }
+}
+
+/// The replacements of a simultaneous substitution, each with its free variables.
+type Replacements<'a> = HashMap<&'a str, (Subtree<'a, String>, HashSet<String>)>;
+
+fn replacements<'a>(subst: &'a [(String, Tree<String>)], symdb: &SymDB) -> Replacements<'a> {
+    subst.iter()
+        .map(|(var, replace)| (var.as_str(), (replace.subtree_root(), free_variables(replace.subtree_root(), symdb))))
+        .collect()
+}
+
+/// Replaces the free occurrences of the variables of `subst` in the subtree `id` of `form` simultaneously.
+/// `binders` are the quantifiers above `id` with their bound variables.
+/// Fails with the ID of the quantifier that would capture a free variable of a replacement.
+fn replace_vars(form: &mut Tree<String>, id: usize, subst: &Replacements, binders: &mut Vec<(usize, String)>)
+-> Result<(), usize> {
+    let node = form.get_node(id).unwrap();
+    if node.is_leaf() {
+        if let Some((replace, fvs)) = subst.get(node.value().as_str()) {
+            if let Some(&(quantifier, _)) = binders.iter().find(|(_, binder)| fvs.contains(binder)) {
+                return Err(quantifier);
+            }
+            form.paste(id, *replace);
+        }
+        return Ok(());
+    }
+    let value = node.value();
+    let mut args = form.children(id);
+    if value == "∀" || value == "∃" {
+        let binder = form.get(args.next().unwrap()).unwrap().clone();
+        let body = args.next().unwrap();
+        let shadowed;
+        let subst = if subst.contains_key(binder.as_str()) {
+            shadowed = subst.iter()
+                .filter(|(var, _)| **var != binder)
+                .map(|(var, replace)| (*var, replace.clone()))
+                .collect();
+            &shadowed
+        } else {
+            subst
+        };
+        binders.push((id, binder));
+        let result = replace_vars(form, body, subst, binders);
+        binders.pop();
+        return result;
+    }
+    let args: Vec<usize> = args.collect();
+    for arg in args {
+        replace_vars(form, arg, subst, binders)?;
+    }
+    Ok(())
+}
+
+fn substitute_replacements(form: &Tree<String>, subst: &Replacements) -> Result<Tree<String>, String> {
+    let mut new_form = form.clone();
+    let root_id = new_form.root_id();
+    replace_vars(&mut new_form, root_id, subst, &mut Vec::new()).map_err(|quantifier| {
+        let binder = form.children(quantifier).next().unwrap();
+        format!("captured free variable: {}", form.get(binder).unwrap())
+    })?;
+    Ok(new_form)
+}
+
+/// Returns `form` with the free occurrences of `var` replaced by `replace`.
+pub fn substitute(form: &Tree<String>, var: &str, replace: Subtree<'_, String>, symdb: &SymDB) -> Result<Tree<String>, String> {
+    let subst = HashMap::from([(var, (replace, free_variables(replace, symdb)))]);
+    substitute_replacements(form, &subst)
+}
+
+/// Returns `form` with the free occurrences of the variables in `subst` replaced simultaneously,
+/// failing if a free variable of a replacement would be captured by a binder.
+pub fn substitute_all(form: &Tree<String>, subst: &[(String, Tree<String>)], symdb: &SymDB) -> Result<Tree<String>, String> {
+    substitute_replacements(form, &replacements(subst, symdb))
+}
+
+/// Finds the term that `form` has in place of the free occurrences of `var` in `target`.
+/// Leaves `term` as `None` if `var` does not occur free in `form`.
+pub fn find_instance(form: Subtree<'_, String>, var: &str, target: Subtree<'_, String>, term: &mut Option<Tree<String>>) -> bool {
+    let value = form.get_root();
+    if form.is_leaf() && value == var {
+        return match term {
+            Some(term) => term.subtree_root() == target,
+            None => {
+                *term = Some(target.to_owned());
+                true
+            }
+        };
+    }
+    if value != target.get_root() || form.children_len() != target.children_len() {
+        return false;
+    }
+    if (value == "∀" || value == "∃") && form.children_root().next().unwrap().get_root() == var {
+        return form == target;
+    }
+    form.children_root().zip(target.children_root())
+        .all(|(arg, target_arg)| find_instance(arg, var, target_arg, term))
+}
+
+/// Same as `substitute_all`, but renames a binder that would capture a free variable of a replacement
+/// to a fresh variable, as in the textbook definition of substitution.
+pub fn substitute_all_renaming(form: &Tree<String>, subst: &[(String, Tree<String>)], symdb: &SymDB) -> Tree<String> {
+    let mut used: HashSet<String> = form.ids().map(|id| form.get(id).unwrap().clone()).collect();
+    for (var, replace) in subst {
+        used.insert(var.clone());
+        used.extend(replace.ids().map(|id| replace.get(id).unwrap().clone()));
+    }
+    let subst = replacements(subst, symdb);
+    let mut form = form.clone();
+    loop {
+        let mut new_form = form.clone();
+        let root_id = new_form.root_id();
+        let Err(quantifier) = replace_vars(&mut new_form, root_id, &subst, &mut Vec::new()) else {
+            return new_form;
+        };
+        let mut args = form.children(quantifier);
+        let (binder, body) = (args.next().unwrap(), args.next().unwrap());
+        let old = form.get(binder).unwrap().clone();
+        let fresh = (1..).map(|i| format!("{}{}", old, i)).find(|var| !used.contains(var)).unwrap();
+        used.insert(fresh.clone());
+        // No binder captures the fresh variable.
+        let leaf = Tree::new(fresh.clone());
+        let rename = HashMap::from([(old.as_str(), (leaf.subtree_root(), HashSet::from([fresh.clone()])))]);
+        replace_vars(&mut form, body, &rename, &mut Vec::new()).unwrap();
+        *form.get_mut(binder).unwrap() = fresh;
+    }
+}
+
+/// Returns the IDs of the free occurrences of the term `term` in `form` in preorder,
+/// each with the variables bound at that position.
+pub fn term_occurrences(form: &Tree<String>, term: Subtree<'_, String>, symdb: &SymDB) -> Vec<(usize, Vec<String>)> {
+    let mut occurrences = Vec::new();
+    let fvs = free_variables(term, symdb);
+    term_occurrences_sub(form, form.root_id(), term, &fvs, &mut Vec::new(), &mut occurrences);
+    occurrences
+}
+
+fn term_occurrences_sub(form: &Tree<String>, id: usize, term: Subtree<'_, String>, fvs: &HashSet<String>,
+binders: &mut Vec<String>, occurrences: &mut Vec<(usize, Vec<String>)>) {
+    if form.subtree(id) == term {
+        if !binders.iter().any(|binder| fvs.contains(binder)) {
+            occurrences.push((id, binders.clone()));
+        }
+        return;
+    }
+    let value = form.get(id).unwrap();
+    let mut args = form.children(id);
+    if value == "∀" || value == "∃" {
+        let binder = form.get(args.next().unwrap()).unwrap().clone();
+        binders.push(binder);
+        term_occurrences_sub(form, args.next().unwrap(), term, fvs, binders, occurrences);
+        binders.pop();
+        return;
+    }
+    for arg in args {
+        term_occurrences_sub(form, arg, term, fvs, binders, occurrences);
+    }
+}
+
+/// Whether `lhs` and `rhs` are equal up to renaming of bound variables.
+/// A bound variable is compared by the distance to its binder, as in the de Bruijn notation.
+pub fn alpha_eq(lhs: Subtree<'_, String>, rhs: Subtree<'_, String>) -> bool {
+    alpha_eq_sub(lhs, rhs, &mut Vec::new(), &mut Vec::new())
+}
+
+fn alpha_eq_sub(lhs: Subtree<String>, rhs: Subtree<String>,
+binders_lhs: &mut Vec<String>, binders_rhs: &mut Vec<String>) -> bool {
+    let (value_lhs, value_rhs) = (lhs.get_root(), rhs.get_root());
+    if lhs.is_leaf() && rhs.is_leaf() {
+        let index_lhs = binders_lhs.iter().rposition(|binder| binder == value_lhs);
+        let index_rhs = binders_rhs.iter().rposition(|binder| binder == value_rhs);
+        return match (index_lhs, index_rhs) {
+            (None, None) => value_lhs == value_rhs,
+            (Some(i), Some(j)) => binders_lhs.len() - i == binders_rhs.len() - j,
+            _ => false,
+        };
+    }
+    if value_lhs != value_rhs || lhs.children_len() != rhs.children_len() {
+        return false;
+    }
+    let mut args_lhs = lhs.children_root();
+    let mut args_rhs = rhs.children_root();
+    if value_lhs == "∀" || value_lhs == "∃" {
+        binders_lhs.push(args_lhs.next().unwrap().get_root().clone());
+        binders_rhs.push(args_rhs.next().unwrap().get_root().clone());
+        let result = alpha_eq_sub(args_lhs.next().unwrap(), args_rhs.next().unwrap(), binders_lhs, binders_rhs);
+        binders_lhs.pop();
+        binders_rhs.pop();
+        return result;
+    }
+    args_lhs.zip(args_rhs).all(|(arg_lhs, arg_rhs)| alpha_eq_sub(arg_lhs, arg_rhs, binders_lhs, binders_rhs))
+}
+
+/// Renames the variables `old` bound in `form` to `new`,
+/// failing if `new` would capture a free variable or be captured.
+pub fn rename_bound(form: Subtree<'_, String>, old: &str, new: &str, symdb: &SymDB) -> Result<Tree<String>, String> {
+    let value = form.get_root();
+    let mut tree = Tree::new(value.clone());
+    let mut args = form.children_root();
+    if (value == "∀" || value == "∃") && form.children_root().next().unwrap().get_root() == old {
+        args.next();
+        let body = rename_bound(args.next().unwrap(), old, new, symdb)?;
+        if free_variables(body.subtree_root(), symdb).contains(new) {
+            return Err(format!("{} occurs free in {}", new, symdb.ast_to_string_minimal(&body)));
+        }
+        let body = substitute(&body, old, Tree::new(new.to_owned()).subtree_root(), symdb)?;
+        tree.push(tree.root_id(), new.to_owned());
+        tree.push_tree(tree.root_id(), body);
+        return Ok(tree);
+    }
+    for arg in args {
+        tree.push_tree(tree.root_id(), rename_bound(arg, old, new, symdb)?);
+    }
+    Ok(tree)
+}
+
+/// The inference that produced a formula in `System::proofs`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Axiom,
+    Mp(usize, usize),
+    Inst(usize, Vec<(String, Tree<String>)>),
+    UqElim(usize),
+    UqIntr(usize, String),
+    UqDistr(String, Tree<String>, Tree<String>),
+    EqRefl(Tree<String>),
+    EqSym(usize),
+    EqTrans(usize, usize),
+    /// `s = t` (the first ID) and φ[s] (the second) derive φ[t],
+    /// replacing the free occurrences of `s` with the given indices.
+    EqSubst(usize, usize, Vec<usize>),
+    /// φ[0] (the first ID) and `φ -> φ[S x]` (the second) derive `x ∀ φ`.
+    Induct(String, usize, usize),
+    /// φ[t] derives `x ∃ φ`, the given formula.
+    ExIntr(usize, Tree<String>),
+    /// `x ∃ φ` (the first ID) and `φ[y] -> C` (the second) derive `C`.
+    ExElim(usize, usize),
+    /// φ derives φ with the bound variable `old` (the first name) renamed to `new` (the second).
+    Rename(usize, String, String),
+}
+
+impl Rule {
+    /// IDs of the proofs this step was derived from.
+    pub fn premises(&self) -> Vec<usize> {
+        match *self {
+            Rule::Axiom | Rule::UqDistr(..) | Rule::EqRefl(_) => Vec::new(),
+            Rule::Mp(id_antec, id_imply) => vec![id_antec, id_imply],
+            Rule::Inst(id, _) | Rule::UqElim(id) | Rule::UqIntr(id, _) | Rule::EqSym(id) | Rule::ExIntr(id, _)
+            | Rule::Rename(id, _, _) => vec![id],
+            Rule::EqTrans(id1, id2) | Rule::EqSubst(id1, id2, _) | Rule::Induct(_, id1, id2)
+            | Rule::ExElim(id1, id2) => vec![id1, id2],
+        }
+    }
+
+    /// The same rule with the premises renumbered by `f`.
+    pub fn remap(&self, f: impl Fn(usize) -> usize) -> Rule {
+        match self.clone() {
+            rule @ (Rule::Axiom | Rule::UqDistr(..) | Rule::EqRefl(_)) => rule,
+            Rule::Mp(id_antec, id_imply) => Rule::Mp(f(id_antec), f(id_imply)),
+            Rule::Inst(id, subst) => Rule::Inst(f(id), subst),
+            Rule::UqElim(id) => Rule::UqElim(f(id)),
+            Rule::UqIntr(id, var) => Rule::UqIntr(f(id), var),
+            Rule::EqSym(id) => Rule::EqSym(f(id)),
+            Rule::EqTrans(id1, id2) => Rule::EqTrans(f(id1), f(id2)),
+            Rule::EqSubst(id_eq, id, positions) => Rule::EqSubst(f(id_eq), f(id), positions),
+            Rule::Induct(var, id_base, id_step) => Rule::Induct(var, f(id_base), f(id_step)),
+            Rule::ExIntr(id, form) => Rule::ExIntr(f(id), form),
+            Rule::ExElim(id_ex, id_imply) => Rule::ExElim(f(id_ex), f(id_imply)),
+            Rule::Rename(id, old, new) => Rule::Rename(f(id), old, new),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Proof {
+    pub form: Tree<String>,
+    pub rule: Rule,
+}
+
+/// The `proofs`, `axioms` and `constants` of a `System`, restored by `undo`.
+type State = (Vec<Proof>, Vec<Tree<String>>, HashSet<String>);
+
 pub struct System {
-    proofs: Vec<Tree<String>>,
+    axioms: Vec<Tree<String>>,
+    proofs: Vec<Proof>,
     parser: Parser,
+    rules: Vec<rewrite::RewriteRule>,
+    /// Whether `inst` renames binders that would capture a variable instead of failing.
+    auto_rename: bool,
+    /// Names of constants imported from TPTP, which are not variables.
+    constants: HashSet<String>,
+    /// `proofs`, `axioms` and `constants` before each command that changed them.
+    history: History<State>,
+    /// The file of the operator templates for `latex`.
+    latex_templates: String,
 }
 
 impl System {
@@ -16,111 +341,85 @@ impl System {
     pub fn new(op_file: &str, axiom_file: &str) -> Result<Self, String> {
         let mut parser = Parser::new(op_file)?;
         let reader_ax = BufReader::new(File::open(axiom_file).map_err(|err| err.to_string())?);
-        let mut proofs = Vec::new();
+        let mut axioms = Vec::new();
         for line in reader_ax.lines() {
             let line = line.map_err(|err| err.to_string())?;
             if line.is_empty() {
                 continue;
             }
-            proofs.push(parser.parse(&line)?);
+            axioms.push(parser.parse(&line)?);
         }
-        Ok(Self { proofs, parser })
+        let proofs = axioms.iter()
+            .map(|axiom| Proof { form: axiom.clone(), rule: Rule::Axiom })
+            .collect();
+        Ok(Self {
+            axioms, proofs, parser, rules: Vec::new(), auto_rename: false, constants: HashSet::new(),
+            history: History::default(),
+            latex_templates: "latex.txt".to_string(),
+        })
     }
 
-    pub fn free_variables(&self, form: Subtree<'_, String>) -> HashSet<String> {
-        let mut fvs = HashSet::new();
-        let mut except = HashSet::new();
-        self.free_variables_sub(form, &mut fvs, &mut except);
-        fvs
-    }
-    pub fn free_variables_sub(&self, form: Subtree<String>, fvs: &mut HashSet<String>, except: &mut HashSet<String>) {
-        if form.is_leaf() {
-            let var = form.get_root();
-            if !except.contains(var) {
-                if self.parser.symdb().is_variable(var) {
-                    fvs.insert(var.clone());
-                }
-            }
-            return;
-        }
-        let value = form.get_root();
-        let mut args = form.children_root();
-        if value == "∀" || value == "∃" {
-            except.insert(args.next().unwrap().get_root().clone());
-            self.free_variables_sub(args.next().unwrap(), fvs, except);
-            return;
-        }
-        for arg in args {
-            self.free_variables_sub(arg, fvs, except);
-        }
+    /// Sets the file of the operator templates for `latex`, `latex.txt` by default.
+    pub fn set_latex_templates(&mut self, file_name: &str) {
+        self.latex_templates = file_name.to_string();
     }
 
-    pub fn replace_var(&self, form: &mut Tree<String>, id: usize, var: &str,
-    replace: Subtree<'_, String>, fvs: &HashSet<String>, binders: &mut HashSet<String>)
-    -> Result<(), String> {
-        let node = form.get_node(id).unwrap();
-        if node.is_leaf() {
-            if node.value() == var {
-                if let Some(binder) = binders.iter().find(|&binder| fvs.contains(binder)) {
-                    return Err(format!("captured free variable: {}", binder));
-                }
-                form.paste(id, replace);
-            }
-            return Ok(());
-        }
-        let value = node.value();
-        let mut args = form.children_root();
-        if value == "∀" || value == "∃" {
-            let binder = form.get(args.next().unwrap()).unwrap();
-            if binder == var {
-                return Ok(());
-            }
-            binders.insert(binder.clone());
-            return self.replace_var(form, args.next().unwrap(), var, replace, fvs, binders);
-        }
-        let args: Vec<usize> = args.collect();
-        for arg in args {
-            self.replace_var(form, arg, var, replace, fvs, binders)?;
-        }
-        Ok(())
+    pub fn get(&self, id: usize) -> Option<&Proof> {
+        self.proofs.get(id)
+    }
+
+    fn push(&mut self, form: Tree<String>, rule: Rule) -> usize {
+        let id = self.proofs.len();
+        self.proofs.push(Proof { form, rule });
+        id
+    }
+
+    /// Whether `name` is a variable, that is, neither an operator, a numeral nor a constant.
+    pub fn is_variable(&self, name: &str) -> bool {
+        self.parser.symdb().is_variable(name) && !self.constants.contains(name)
+    }
+
+    pub fn free_variables(&self, form: Subtree<'_, String>) -> HashSet<String> {
+        free_variables(form, self.parser.symdb())
     }
 
-    pub fn inst(&mut self, id: usize, var: &str, replace: Subtree<'_, String>) -> Result<usize, String> {
+    /// Substitutes the free variables in `subst` simultaneously,
+    /// renaming the binders that would capture a variable if `auto_rename` is set.
+    pub fn inst(&mut self, id: usize, subst: &[(String, Tree<String>)]) -> Result<usize, String> {
         let Some(proof) = self.proofs.get(id) else {
             return Err(format!("there is no proof with ID {}", id));
         };
-        let mut new_proof = proof.clone();
-        let root_id = new_proof.root_id();
-        self.replace_var(&mut new_proof, root_id, var, replace, &self.free_variables(replace), &mut HashSet::new())?;
-        let id = self.proofs.len();
-        self.proofs.push(new_proof);
-        Ok(id)
+        if let Some((var, _)) = subst.iter().find(|(var, _)| !self.is_variable(var)) {
+            return Err(format!("{} is not a variable", var));
+        }
+        let new_proof = if self.auto_rename {
+            substitute_all_renaming(&proof.form, subst, self.parser.symdb())
+        } else {
+            substitute_all(&proof.form, subst, self.parser.symdb())?
+        };
+        Ok(self.push(new_proof, Rule::Inst(id, subst.to_vec())))
     }
 
     pub fn uq_elim(&mut self, id: usize) -> Result<usize, String> {
         let Some(proof) = self.proofs.get(id) else {
             return Err(format!("there is no proof with ID {}", id));
         };
-        let map = self.parser.pattern_match(proof.subtree_root(), "_ ∀ @a")?;
+        let map = self.parser.pattern_match(proof.form.subtree_root(), "_ ∀ @a")?;
         let inner = map["a"].clone();
-        let id = self.proofs.len();
-        self.proofs.push(inner);
-        Ok(id)
+        Ok(self.push(inner, Rule::UqElim(id)))
     }
 
     pub fn uq_intr(&mut self, id: usize, var: &str) -> Result<usize, String> {
         let Some(proof) = self.proofs.get(id) else {
             return Err(format!("there is no proof with ID {}", id));
         };
-        if !self.parser.symdb().is_variable(var) {
+        if !self.is_variable(var) {
             return Err(format!("invalid variable name: {}", var));
         }
         let mut new_proof = Tree::new("∀".to_owned());
         new_proof.push(new_proof.root_id(), var.to_owned());
-        new_proof.push_sub(new_proof.root_id(), proof.subtree_root());
-        let id = self.proofs.len();
-        self.proofs.push(new_proof);
-        Ok(id)
+        new_proof.push_sub(new_proof.root_id(), proof.form.subtree_root());
+        Ok(self.push(new_proof, Rule::UqIntr(id, var.to_string())))
     }
 
     pub fn mp(&mut self, id_antec: usize, id_imply: usize) -> Result<usize, String> {
@@ -130,31 +429,213 @@ impl System {
         let Some(proof_imply) = self.proofs.get(id_imply) else {
             return Err(format!("there is no proof with ID {}", id_imply));
         };
+        let proof_imply = &proof_imply.form;
         if proof_imply.get_root().unwrap() != "->" {
             return Err("a mismatched form '[a] -> [b]'".to_string());
         }
         let mut operands = proof_imply.children_root();
         let antecedent = proof_imply.subtree(operands.next().unwrap());
-        if proof_antec.subtree_root() != antecedent {
+        if !alpha_eq(proof_antec.form.subtree_root(), antecedent) {
             return Err("a mismatched pattern '[a], [a] -> [b]'".to_string());
         }
         let consequent = proof_imply.clone_sub(operands.next().unwrap());
-        let id = self.proofs.len();
-        self.proofs.push(consequent);
-        Ok(id)
+        Ok(self.push(consequent, Rule::Mp(id_antec, id_imply)))
     }
 
     pub fn uq_distr(&mut self, var: &str, form1: &str, form2: &str) -> Result<usize, String> {
-        if !self.parser.symdb().is_variable(var) {
+        if !self.is_variable(var) {
             return Err(format!("invalid variable name: {}", var));
         }
+        let rule = Rule::UqDistr(var.to_string(), self.parser.parse(form1)?, self.parser.parse(form2)?);
         let f1 = format!("({})", form1);
         let f2 = format!("({})", form2);
         let s = format!("({var}∀ {f1} -> {f2}) -> ({var}∀{f1}) -> ({var}∀{f2})");
         let new_proof = self.parser.parse(&s)?;
-        let id = self.proofs.len();
-        self.proofs.push(new_proof);
-        Ok(id)
+        Ok(self.push(new_proof, rule))
+    }
+
+    /// Splits `s = t` (`id`) into `s` and `t`.
+    fn equation(&mut self, id: usize) -> Result<(Tree<String>, Tree<String>), String> {
+        let Some(proof) = self.proofs.get(id) else {
+            return Err(format!("there is no proof with ID {}", id));
+        };
+        let map = self.parser.pattern_match(proof.form.subtree_root(), "@a = @b")?;
+        Ok((map["a"].clone(), map["b"].clone()))
+    }
+
+    fn equal(lhs: Tree<String>, rhs: Tree<String>) -> Tree<String> {
+        let mut form = Tree::new("=".to_owned());
+        form.push_tree(form.root_id(), lhs);
+        form.push_tree(form.root_id(), rhs);
+        form
+    }
+
+    pub fn eq_refl(&mut self, term: Tree<String>) -> usize {
+        let new_proof = Self::equal(term.clone(), term.clone());
+        self.push(new_proof, Rule::EqRefl(term))
+    }
+
+    pub fn eq_sym(&mut self, id: usize) -> Result<usize, String> {
+        let (lhs, rhs) = self.equation(id)?;
+        Ok(self.push(Self::equal(rhs, lhs), Rule::EqSym(id)))
+    }
+
+    pub fn eq_trans(&mut self, id1: usize, id2: usize) -> Result<usize, String> {
+        let (lhs1, rhs1) = self.equation(id1)?;
+        let (lhs2, rhs2) = self.equation(id2)?;
+        if rhs1 != lhs2 {
+            return Err("a mismatched pattern '[a] = [b], [b] = [c]'".to_string());
+        }
+        Ok(self.push(Self::equal(lhs1, rhs2), Rule::EqTrans(id1, id2)))
+    }
+
+    /// From `s = t` (`id_eq`) and φ[s] (`id`), derives φ[t] by replacing the free occurrences of `s`
+    /// with the indices `positions`, counted from 0 in preorder, or all of them if `positions` is empty.
+    pub fn eq_subst(&mut self, id_eq: usize, id: usize, positions: &[usize]) -> Result<usize, String> {
+        let (lhs, rhs) = self.equation(id_eq)?;
+        let Some(proof) = self.proofs.get(id) else {
+            return Err(format!("there is no proof with ID {}", id));
+        };
+        let symdb = self.parser.symdb();
+        let occurrences = term_occurrences(&proof.form, lhs.subtree_root(), symdb);
+        if occurrences.is_empty() {
+            return Err(format!("{} does not occur free in {}",
+                symdb.ast_to_string_minimal(&lhs), symdb.ast_to_string_minimal(&proof.form)));
+        }
+        let positions: Vec<_> = if positions.is_empty() {
+            (0..occurrences.len()).collect()
+        } else {
+            let mut positions = positions.to_vec();
+            positions.sort_unstable();
+            positions.dedup();
+            positions
+        };
+        let fvs = free_variables(rhs.subtree_root(), symdb);
+        let mut new_proof = proof.form.clone();
+        for &position in positions.iter() {
+            let Some((node, binders)) = occurrences.get(position) else {
+                return Err(format!("there are only {} occurrences", occurrences.len()));
+            };
+            if let Some(binder) = binders.iter().find(|&binder| fvs.contains(binder)) {
+                return Err(format!("captured free variable: {}", binder));
+            }
+            new_proof.paste(*node, rhs.subtree_root());
+        }
+        Ok(self.push(new_proof, Rule::EqSubst(id_eq, id, positions)))
+    }
+
+    /// Derives `form`, which is `x ∃ A`, from A with a term in place of `x` (`id`).
+    pub fn ex_intr(&mut self, id: usize, form: Tree<String>) -> Result<usize, String> {
+        let Some(proof) = self.proofs.get(id) else {
+            return Err(format!("there is no proof with ID {}", id));
+        };
+        let map = self.parser.pattern_match(form.subtree_root(), "@x ∃ @a")?;
+        let var = map["x"].get_root().unwrap();
+        let mut term = None;
+        if !find_instance(map["a"].subtree_root(), var, proof.form.subtree_root(), &mut term) {
+            return Err(format!("{} is not an instance", id));
+        }
+        let term = term.unwrap_or_else(|| map["x"].clone());
+        let instance = substitute(&map["a"], var, term.subtree_root(), self.parser.symdb())?;
+        if !alpha_eq(instance.subtree_root(), proof.form.subtree_root()) {
+            return Err(format!("{} is not an instance", id));
+        }
+        Ok(self.push(form.clone(), Rule::ExIntr(id, form)))
+    }
+
+    /// Derives C from `x ∃ A` (`id_ex`) and `A[y] -> C` (`id_imply`),
+    /// where the eigenvariable `y` is free neither in C nor, unless it is `x`, in `x ∃ A`.
+    pub fn ex_elim(&mut self, id_ex: usize, id_imply: usize) -> Result<usize, String> {
+        for id in [id_ex, id_imply] {
+            if id >= self.proofs.len() {
+                return Err(format!("there is no proof with ID {}", id));
+            }
+        }
+        let map = self.parser.pattern_match(self.proofs[id_ex].form.subtree_root(), "@x ∃ @a")?;
+        let map_imply = self.parser.pattern_match(self.proofs[id_imply].form.subtree_root(), "@a -> @c")?;
+        let symdb = self.parser.symdb();
+        let var = map["x"].get_root().unwrap();
+        let mut term = None;
+        if !find_instance(map["a"].subtree_root(), var, map_imply["a"].subtree_root(), &mut term) {
+            return Err("a mismatched pattern 'x ∃ [a], [a] -> [c]'".to_string());
+        }
+        let eigenvar = term.unwrap_or_else(|| map["x"].clone());
+        let name = eigenvar.get_root().unwrap();
+        if !eigenvar.subtree_root().is_leaf() || !self.is_variable(name) {
+            return Err(format!("{} is not a variable", symdb.ast_to_string_minimal(&eigenvar)));
+        }
+        let instance = substitute(&map["a"], var, eigenvar.subtree_root(), symdb)?;
+        if !alpha_eq(instance.subtree_root(), map_imply["a"].subtree_root()) {
+            return Err("a mismatched pattern 'x ∃ [a], [a] -> [c]'".to_string());
+        }
+        let conclusion = &map_imply["c"];
+        if free_variables(conclusion.subtree_root(), symdb).contains(name) {
+            return Err(format!("{} occurs free in {}", name, symdb.ast_to_string_minimal(conclusion)));
+        }
+        if name != var && free_variables(self.proofs[id_ex].form.subtree_root(), symdb).contains(name) {
+            return Err(format!("{} occurs free in {}", name, symdb.ast_to_string_minimal(&self.proofs[id_ex].form)));
+        }
+        Ok(self.push(conclusion.clone(), Rule::ExElim(id_ex, id_imply)))
+    }
+
+    /// From φ[0] (`id_base`) and `φ -> φ[S var]` (`id_step`), derives `var ∀ φ` by induction.
+    pub fn induct(&mut self, var: &str, id_base: usize, id_step: usize) -> Result<usize, String> {
+        for id in [id_base, id_step] {
+            if id >= self.proofs.len() {
+                return Err(format!("there is no proof with ID {}", id));
+            }
+        }
+        let symdb = self.parser.symdb();
+        if !self.is_variable(var) {
+            return Err(format!("invalid variable name: {}", var));
+        }
+        let form_step = &self.proofs[id_step].form;
+        if form_step.get_root().unwrap() != "->" {
+            return Err("a mismatched form '[a] -> [b]'".to_string());
+        }
+        let mut operands = form_step.children_root();
+        let form = form_step.clone_sub(operands.next().unwrap());
+        let mut succ = Tree::new("S".to_owned());
+        succ.push(succ.root_id(), var.to_owned());
+        let succ = substitute(&form, var, succ.subtree_root(), symdb)?;
+        if !alpha_eq(succ.subtree_root(), form_step.subtree(operands.next().unwrap())) {
+            return Err(format!("the step is not of the form 'A -> A[S {} / {}]'", var, var));
+        }
+        let base = substitute(&form, var, Tree::new("0".to_owned()).subtree_root(), symdb)?;
+        if !alpha_eq(base.subtree_root(), self.proofs[id_base].form.subtree_root()) {
+            return Err(format!("the base is not of the form 'A[0 / {}]'", var));
+        }
+        let mut new_proof = Tree::new("∀".to_owned());
+        new_proof.push(new_proof.root_id(), var.to_owned());
+        new_proof.push_tree(new_proof.root_id(), form);
+        Ok(self.push(new_proof, Rule::Induct(var.to_string(), id_base, id_step)))
+    }
+
+    /// Renames the variables `old` bound in the proof `id` to `new`.
+    pub fn rename(&mut self, id: usize, old: &str, new: &str) -> Result<usize, String> {
+        let Some(proof) = self.proofs.get(id) else {
+            return Err(format!("there is no proof with ID {}", id));
+        };
+        let symdb = self.parser.symdb();
+        if !self.is_variable(new) {
+            return Err(format!("invalid variable name: {}", new));
+        }
+        let form = &proof.form;
+        let is_bound = form.ids().any(|id| {
+            let value = form.get(id).unwrap();
+            (value == "∀" || value == "∃") && form.get(form.children(id).next().unwrap()).unwrap() == old
+        });
+        if !is_bound {
+            return Err(format!("{} is not bound in {}", old, symdb.ast_to_string_minimal(form)));
+        }
+        let new_proof = rename_bound(form.subtree_root(), old, new, symdb)?;
+        Ok(self.push(new_proof, Rule::Rename(id, old.to_string(), new.to_string())))
+    }
+
+    /// Returns the ID of the first proof before `id` that is alpha-equivalent to `id`.
+    pub fn duplicate(&self, id: usize) -> Option<usize> {
+        let form = self.proofs.get(id)?.form.subtree_root();
+        self.proofs[..id].iter().position(|proof| alpha_eq(proof.form.subtree_root(), form))
     }
 
     pub fn print_proof(&self, id: usize) {
@@ -162,44 +643,349 @@ impl System {
         let Some(proof) = self.proofs.get(id) else {
             return println!("an invalid proof ID: {}", id);
         };
-        println!("{}: {}", id, symdb.ast_to_string_minimal(proof));
+        match self.duplicate(id) {
+            Some(dup) => println!("{}: {} (same as {})", id, symdb.ast_to_string_minimal(&proof.form), dup),
+            None => println!("{}: {}", id, symdb.ast_to_string_minimal(&proof.form)),
+        }
+    }
+
+    pub fn rule_to_string(&self, rule: &Rule) -> String {
+        let symdb = self.parser.symdb();
+        match rule {
+            Rule::Axiom => "axiom".to_string(),
+            Rule::Mp(id_antec, id_imply) => format!("mp {} {}", id_antec, id_imply),
+            Rule::Inst(id, subst) => format!("inst {} {}", id, subst_to_string(subst, symdb)),
+            Rule::UqElim(id) => format!("uq-elim {}", id),
+            Rule::UqIntr(id, var) => format!("uq-intr {} {}", id, var),
+            Rule::UqDistr(var, form1, form2) =>
+                format!("uq-distr {} {} ; {}", var,
+                    symdb.ast_to_string_minimal(form1), symdb.ast_to_string_minimal(form2)),
+            Rule::EqRefl(term) => format!("eq-refl {}", symdb.ast_to_string_minimal(term)),
+            Rule::EqSym(id) => format!("eq-sym {}", id),
+            Rule::EqTrans(id1, id2) => format!("eq-trans {} {}", id1, id2),
+            Rule::EqSubst(id_eq, id, positions) => {
+                let positions: Vec<String> = positions.iter().map(|position| position.to_string()).collect();
+                format!("eq-subst {} {} {}", id_eq, id, positions.join(" "))
+            }
+            Rule::Induct(var, id_base, id_step) => format!("induct {} {} {}", var, id_base, id_step),
+            Rule::ExIntr(id, form) => format!("ex-intr {} {}", id, symdb.ast_to_string_minimal(form)),
+            Rule::ExElim(id_ex, id_imply) => format!("ex-elim {} {}", id_ex, id_imply),
+            Rule::Rename(id, old, new) => format!("rename {} {} {}", id, old, new),
+        }
+    }
+
+    /// Formats a proof as `ID: formula [rule]`.
+    pub fn proof_to_string(&self, id: usize) -> Option<String> {
+        let symdb = self.parser.symdb();
+        let proof = self.proofs.get(id)?;
+        Some(format!("{}: {} [{}]", id, symdb.ast_to_string_minimal(&proof.form), self.rule_to_string(&proof.rule)))
     }
 
     pub fn print_proofs(&self) {
+        for id in 0..self.proofs.len() {
+            println!("{}", self.proof_to_string(id).unwrap());
+        }
+    }
+
+    /// Adds the formulas of a TPTP problem as axioms, except conjectures, which are returned as goals.
+    /// Lower words become constants, renamed to `[name]1`, `[name]2`, ... if the proofs use them as variables.
+    pub fn import_tptp(&mut self, input: &str) -> Result<(Vec<usize>, Vec<tptp::Annotated>), String> {
+        let mut axioms = Vec::new();
+        let mut goals = Vec::new();
+        for annotated in tptp::parse_problem(input)? {
+            let symdb = self.parser.symdb();
+            let form = &annotated.form;
+            let applied_var = form.ids()
+                .find(|&id| !form.get_node(id).unwrap().is_leaf() && symdb.is_variable(form.get(id).unwrap()));
+            if let Some(id) = applied_var {
+                return Err(format!("{}: {} is not an operator", annotated.name, form.get(id).unwrap()));
+            }
+            match annotated.role.as_str() {
+                "axiom" | "hypothesis" | "definition" | "assumption" | "lemma" | "theorem" | "corollary"
+                | "negated_conjecture" => axioms.push(annotated.form),
+                "conjecture" => goals.push(annotated),
+                other => return Err(format!("{}: unsupported role: {}", annotated.name, other)),
+            }
+        }
+        let symdb = self.parser.symdb();
+        let used: HashSet<String> = self.proofs.iter()
+            .flat_map(|proof| proof.form.ids().map(|id| proof.form.get(id).unwrap().clone()))
+            .filter(|name| !self.constants.contains(name))
+            .collect();
+        let mut taken: HashSet<String> = used.union(&self.constants).cloned().collect();
+        let mut lower_words = BTreeSet::new();
+        for form in axioms.iter().chain(goals.iter().map(|goal| &goal.form)) {
+            taken.extend(form.ids().map(|id| form.get(id).unwrap().clone()));
+            lower_words.extend(free_variables(form.subtree_root(), symdb));
+        }
+        let mut renaming = Vec::new();
+        for word in lower_words {
+            if !used.contains(&word) {
+                self.constants.insert(word);
+                continue;
+            }
+            let fresh = (1..).map(|i| format!("{}{}", word, i)).find(|name| !taken.contains(name)).unwrap();
+            taken.insert(fresh.clone());
+            self.constants.insert(fresh.clone());
+            renaming.push((word, Tree::new(fresh)));
+        }
+        // The fresh names occur nowhere, so no binder captures them.
+        let axioms: Vec<Tree<String>> = axioms.iter()
+            .map(|form| substitute_all(form, &renaming, symdb))
+            .collect::<Result<_, _>>()?;
+        for goal in goals.iter_mut() {
+            goal.form = substitute_all(&goal.form, &renaming, symdb)?;
+        }
+        let ids = axioms.into_iter()
+            .map(|form| {
+                self.axioms.push(form.clone());
+                self.push(form, Rule::Axiom)
+            })
+            .collect();
+        Ok((ids, goals))
+    }
+
+    /// Writes the axioms as a TPTP problem, with `goal` as the conjecture.
+    pub fn export_tptp(&self, goal: Option<&Tree<String>>) -> Result<String, String> {
         let symdb = self.parser.symdb();
-        for (id, proof) in self.proofs.iter().enumerate() {
-            println!("{}: {}", id, symdb.ast_to_string_minimal(proof));
+        let mut s = String::new();
+        for (id, proof) in self.proofs.iter().enumerate().filter(|(_, proof)| proof.rule == Rule::Axiom) {
+            s += &tptp::annotated_to_tptp(&format!("ax{}", id), "axiom", &proof.form, symdb, &self.constants)?;
+            s.push('\n');
+        }
+        if let Some(goal) = goal {
+            s += &tptp::annotated_to_tptp("goal", "conjecture", goal, symdb, &self.constants)?;
+            s.push('\n');
+        }
+        Ok(s)
+    }
+
+    /// Removes the proof `id` and every later proof that depends on it, and renumbers the rest.
+    /// Returns the IDs of the removed proofs.
+    pub fn retract(&mut self, id: usize) -> Result<Vec<usize>, String> {
+        let Some(proof) = self.proofs.get(id) else {
+            return Err(format!("there is no proof with ID {}", id));
+        };
+        if proof.rule == Rule::Axiom {
+            return Err(format!("{} is an axiom", id));
+        }
+        let new_ids = util::retract(&mut self.proofs, id, |proof| proof.rule.premises(),
+            |proof, f| Proof { form: proof.form, rule: proof.rule.remap(f) });
+        Ok((0..new_ids.len()).filter(|&i| new_ids[i].is_none()).collect())
+    }
+
+    /// IDs of all proofs `id` depends on, including `id` itself, in ascending order.
+    pub fn derivation(&self, id: usize) -> Vec<usize> {
+        let mut used = vec![false; id + 1];
+        used[id] = true;
+        for i in (0..=id).rev() {
+            if used[i] {
+                for premise in self.proofs[i].rule.premises() {
+                    used[premise] = true;
+                }
+            }
+        }
+        (0..=id).filter(|&i| used[i]).collect()
+    }
+
+    pub fn print_derivation(&self, id: usize) {
+        if id >= self.proofs.len() {
+            return println!("an invalid proof ID: {}", id);
+        }
+        for i in self.derivation(id) {
+            println!("{}", self.proof_to_string(i).unwrap());
+        }
+    }
+
+    pub fn parse_rule(&mut self, s: &str) -> Result<Rule, String> {
+        let mut args = SplitWhitespace::from(s);
+        let Some(name) = args.next() else { return Err("missing rule".to_string()); };
+        let next_id = |args: &mut SplitWhitespace| -> Result<usize, String> {
+            let id = args.next().ok_or_else(|| format!("missing proof ID: {}", s))?;
+            id.parse().map_err(|_| format!("invalid proof ID: {}", id))
+        };
+        match name {
+            "axiom" => Ok(Rule::Axiom),
+            "mp" => {
+                let id_antec = next_id(&mut args)?;
+                let id_imply = next_id(&mut args)?;
+                Ok(Rule::Mp(id_antec, id_imply))
+            }
+            "inst" => {
+                let id = next_id(&mut args)?;
+                Ok(Rule::Inst(id, parse_subst(&mut self.parser, args.remainder())?))
+            }
+            "uq-elim" => Ok(Rule::UqElim(next_id(&mut args)?)),
+            "uq-intr" => {
+                let id = next_id(&mut args)?;
+                let var = args.next().ok_or_else(|| format!("missing variable: {}", s))?;
+                Ok(Rule::UqIntr(id, var.to_string()))
+            }
+            "uq-distr" => {
+                let var = args.next().ok_or_else(|| format!("missing variable: {}", s))?;
+                let (form1, form2) = args.remainder().split_once(';')
+                    .ok_or_else(|| format!("missing ';': {}", s))?;
+                Ok(Rule::UqDistr(var.to_string(), self.parser.parse(form1)?, self.parser.parse(form2)?))
+            }
+            "eq-refl" => Ok(Rule::EqRefl(self.parser.parse(args.remainder())?)),
+            "eq-sym" => Ok(Rule::EqSym(next_id(&mut args)?)),
+            "eq-trans" => {
+                let id1 = next_id(&mut args)?;
+                let id2 = next_id(&mut args)?;
+                Ok(Rule::EqTrans(id1, id2))
+            }
+            "eq-subst" => {
+                let id_eq = next_id(&mut args)?;
+                let id = next_id(&mut args)?;
+                let positions = args.map(|position| position.parse().map_err(|_| format!("invalid position: {}", position)))
+                    .collect::<Result<Vec<usize>, String>>()?;
+                Ok(Rule::EqSubst(id_eq, id, positions))
+            }
+            "induct" => {
+                let var = args.next().ok_or_else(|| format!("missing variable: {}", s))?;
+                let id_base = next_id(&mut args)?;
+                let id_step = next_id(&mut args)?;
+                Ok(Rule::Induct(var.to_string(), id_base, id_step))
+            }
+            "ex-intr" => {
+                let id = next_id(&mut args)?;
+                Ok(Rule::ExIntr(id, self.parser.parse(args.remainder())?))
+            }
+            "ex-elim" => {
+                let id_ex = next_id(&mut args)?;
+                let id_imply = next_id(&mut args)?;
+                Ok(Rule::ExElim(id_ex, id_imply))
+            }
+            "rename" => {
+                let id = next_id(&mut args)?;
+                let old = args.next().ok_or_else(|| format!("missing variable: {}", s))?;
+                let new = args.next().ok_or_else(|| format!("missing variable: {}", s))?;
+                Ok(Rule::Rename(id, old.to_string(), new.to_string()))
+            }
+            other => Err(format!("unknown rule: {}", other)),
         }
     }
 
     pub fn save(&self, file_name: &str) -> std::io::Result<()> {
         let mut buffer = BufWriter::new(File::create(file_name)?);
-        let symdb = self.parser.symdb();
-        for proof in self.proofs.iter() {
-            let proof_str = symdb.ast_to_string_minimal(proof) + "\n";
+        for id in 0..self.proofs.len() {
+            let proof_str = self.proof_to_string(id).unwrap() + "\n";
             buffer.write_all(proof_str.as_bytes())?;
         }
         buffer.flush()?;
         Ok(())
     }
 
+    /// Derives `form` again by `rule` and checks that the result is `form`.
+    fn replay(&mut self, form: Tree<String>, rule: Rule) -> Result<usize, String> {
+        let id = match rule {
+            Rule::Axiom => {
+                if !self.axioms.contains(&form) {
+                    return Err("not an axiom".to_string());
+                }
+                self.push(form.clone(), Rule::Axiom)
+            }
+            Rule::Mp(id_antec, id_imply) => self.mp(id_antec, id_imply)?,
+            Rule::Inst(id, subst) => self.inst(id, &subst)?,
+            Rule::UqElim(id) => self.uq_elim(id)?,
+            Rule::UqIntr(id, var) => self.uq_intr(id, &var)?,
+            Rule::UqDistr(var, form1, form2) => {
+                let symdb = self.parser.symdb();
+                let form1 = symdb.ast_to_string_minimal(&form1);
+                let form2 = symdb.ast_to_string_minimal(&form2);
+                self.uq_distr(&var, &form1, &form2)?
+            }
+            Rule::EqRefl(term) => self.eq_refl(term),
+            Rule::EqSym(id) => self.eq_sym(id)?,
+            Rule::EqTrans(id1, id2) => self.eq_trans(id1, id2)?,
+            Rule::EqSubst(id_eq, id, positions) => self.eq_subst(id_eq, id, &positions)?,
+            Rule::Induct(var, id_base, id_step) => self.induct(&var, id_base, id_step)?,
+            Rule::ExIntr(id, form) => self.ex_intr(id, form)?,
+            Rule::ExElim(id_ex, id_imply) => self.ex_elim(id_ex, id_imply)?,
+            Rule::Rename(id, old, new) => self.rename(id, &old, &new)?,
+        };
+        if self.proofs[id].form != form {
+            let symdb = self.parser.symdb();
+            return Err(format!("the rule derives {}", symdb.ast_to_string_minimal(&self.proofs[id].form)));
+        }
+        Ok(id)
+    }
+
+    fn load_line(&mut self, line: &str) -> Result<(), String> {
+        let (id, body) = line.split_once(':')
+            .ok_or_else(|| "missing proof ID".to_string())?;
+        let id: usize = id.trim().parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+        if id != self.proofs.len() {
+            return Err(format!("expected proof ID {}, found {}", self.proofs.len(), id));
+        }
+        let (form, rule) = body.split_once('[')
+            .and_then(|(form, rule)| Some((form, rule.trim_end().strip_suffix(']')?)))
+            .ok_or_else(|| "missing rule".to_string())?;
+        let form = self.parser.parse(form)?;
+        let rule = self.parse_rule(rule)?;
+        self.replay(form, rule)?;
+        Ok(())
+    }
+
+    /// Loads a file written by `save`, checking every step against the axioms.
+    /// Substitutions are replayed with renaming, whichever mode they were made in.
+    /// On failure the current proofs are kept.
     pub fn load(&mut self, file_name: &str) -> std::io::Result<()> {
         let buffer = BufReader::new(File::open(file_name)?);
-        self.proofs.clear();
-        for line in buffer.lines() {
-            let proof = self.parser.parse(&line?)
-                .map_err(|err| Error::new(ErrorKind::Other, err))?;
-            self.proofs.push(proof);
+        let old_proofs = std::mem::take(&mut self.proofs);
+        let auto_rename = std::mem::replace(&mut self.auto_rename, true);
+        for (i, line) in buffer.lines().enumerate() {
+            let result = line.map_err(|err| err.to_string())
+                .and_then(|line| self.load_line(&line));
+            if let Err(err) = result {
+                self.proofs = old_proofs;
+                self.auto_rename = auto_rename;
+                return Err(Error::new(ErrorKind::Other, format!("line {}: {}", i + 1, err)));
+            }
         }
+        self.auto_rename = auto_rename;
         Ok(())
     }
 
+    /// Runs `change`, a command step that changes the proofs, and records the state before it for `undo`.
+    fn record<T>(&mut self, change: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        let before = (self.proofs.clone(), self.axioms.clone(), self.constants.clone());
+        let result = change(self)?;
+        self.history.record(before);
+        Ok(result)
+    }
+
+    /// Runs a command. `undo` and `redo` go back and forth over the commands that changed the proofs.
     pub fn command(&mut self, s: &str) -> Result<(), String> {
+        match s.trim() {
+            "undo" | "redo" => {
+                let state = (self.proofs.clone(), self.axioms.clone(), self.constants.clone());
+                let state = if s.trim() == "undo" {
+                    self.history.undo(state).ok_or_else(|| "nothing to undo".to_string())?
+                } else {
+                    self.history.redo(state).ok_or_else(|| "nothing to redo".to_string())?
+                };
+                (self.proofs, self.axioms, self.constants) = state;
+                if let Some(id) = self.proofs.len().checked_sub(1) {
+                    self.print_proof(id);
+                }
+                Ok(())
+            }
+            _ => self.run_command(s),
+        }
+    }
+
+    fn run_command(&mut self, s: &str) -> Result<(), String> {
         let mut args = SplitWhitespace::from(s);
         let Some(cmd) = args.next() else { return Ok(()); };
         match cmd {
             "show" => {
-                self.print_proofs();
+                if let Some(id) = args.next() {
+                    let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                    self.print_derivation(id);
+                } else {
+                    self.print_proofs();
+                }
             }
             "save" => {
                 let file_name = args.next().unwrap_or("default.txt");
@@ -209,30 +995,404 @@ impl System {
             }
             "load" => {
                 let file_name = args.next().unwrap_or("default.txt");
-                if let Err(err) = self.load(file_name) {
-                    return Err(err.to_string());
-                }
+                self.record(|sys| sys.load(file_name).map_err(|err| err.to_string()))?;
             }
             "mp" => {
                 let Some(ant_id) = args.next() else { return Err("E1".to_string()); };
                 let Some(imp_id) = args.next() else { return Err("E2".to_string()); };
                 let ant_id: usize = ant_id.parse().map_err(|_| "E3".to_string())?;
                 let imp_id: usize = imp_id.parse().map_err(|_| "E4".to_string())?;
-                let new_id = self.mp(ant_id, imp_id)?;
+                let new_id = self.record(|sys| sys.mp(ant_id, imp_id))?;
                 self.print_proof(new_id);
             }
             "inst" => {
                 let Some(id) = args.next() else { return Err("".to_string()); };
                 let id: usize = id.parse().map_err(|_| "".to_string())?;
-                let Some(var) = args.next() else { return Err("".to_string()); };
-                let rem = args.remainder();
-                let replace = self.parser.parse(rem).map_err(|_| "".to_string())?;
-                let new_id = self.inst(id, var, replace.subtree_root())?;
+                let subst = parse_subst(&mut self.parser, args.remainder())?;
+                let new_id = self.record(|sys| sys.inst(id, &subst))?;
+                self.print_proof(new_id);
+            }
+            "uq-elim" => {
+                let Some(id) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                let new_id = self.record(|sys| sys.uq_elim(id))?;
+                self.print_proof(new_id);
+            }
+            "uq-intr" => {
+                let Some(id) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                let Some(var) = args.next() else { return Err("missing variable".to_string()); };
+                let new_id = self.record(|sys| sys.uq_intr(id, var))?;
+                self.print_proof(new_id);
+            }
+            "uq-distr" => {
+                let Some(var) = args.next() else { return Err("missing variable".to_string()); };
+                let Some((form1, form2)) = args.remainder().split_once(';') else {
+                    return Err("expected 'uq-distr [variable] [formula] ; [formula]'".to_string());
+                };
+                let new_id = self.record(|sys| sys.uq_distr(var, form1, form2))?;
+                self.print_proof(new_id);
+            }
+            "eq-refl" | "eq-sym" | "eq-trans" | "eq-subst" => {
+                let rule = self.parse_rule(s)?;
+                let new_id = self.record(|sys| match rule {
+                    Rule::EqRefl(term) => Ok(sys.eq_refl(term)),
+                    Rule::EqSym(id) => sys.eq_sym(id),
+                    Rule::EqTrans(id1, id2) => sys.eq_trans(id1, id2),
+                    Rule::EqSubst(id_eq, id, positions) => sys.eq_subst(id_eq, id, &positions),
+                    _ => unreachable!(),
+                })?;
+                self.print_proof(new_id);
+            }
+            "rules" => {
+                if let Some(file_name) = args.next() {
+                    self.rules = rewrite::load_rules(&mut self.parser, file_name)?;
+                }
+                for (i, rule) in self.rules.iter().enumerate() {
+                    println!("{}: {}", i, rewrite::rule_to_string(rule, self.parser.symdb()));
+                }
+            }
+            "rule" => {
+                let rule = rewrite::parse_rule(&mut self.parser, args.remainder())?;
+                println!("{}: {}", self.rules.len(), rewrite::rule_to_string(&rule, self.parser.symdb()));
+                self.rules.push(rule);
+            }
+            "ex-intr" => {
+                let Some(id) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                let form = self.parser.parse(args.remainder())?;
+                let new_id = self.record(|sys| sys.ex_intr(id, form))?;
+                self.print_proof(new_id);
+            }
+            "ex-elim" => {
+                let Some(id_ex) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id_ex: usize = id_ex.parse().map_err(|_| format!("invalid proof ID: {}", id_ex))?;
+                let Some(id_imply) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id_imply: usize = id_imply.parse().map_err(|_| format!("invalid proof ID: {}", id_imply))?;
+                let new_id = self.record(|sys| sys.ex_elim(id_ex, id_imply))?;
+                self.print_proof(new_id);
+            }
+            "auto-rename" => {
+                match args.next() {
+                    Some("on") => self.auto_rename = true,
+                    Some("off") => self.auto_rename = false,
+                    Some(other) => return Err(format!("expected 'on' or 'off': {}", other)),
+                    None => {}
+                }
+                println!("auto-rename: {}", if self.auto_rename { "on" } else { "off" });
+            }
+            "latex" => latex::command(self, &mut args, &self.latex_templates)?,
+            "tptp-import" => {
+                let Some(file_name) = args.next() else { return Err("missing file name".to_string()); };
+                let input = std::fs::read_to_string(file_name).map_err(|err| err.to_string())?;
+                let (ids, goals) = self.record(|sys| sys.import_tptp(&input))?;
+                for id in ids {
+                    self.print_proof(id);
+                }
+                for goal in goals {
+                    println!("conjecture {}: {}", goal.name, self.parser.symdb().ast_to_string_minimal(&goal.form));
+                }
+            }
+            "tptp-export" => {
+                let Some(file_name) = args.next() else { return Err("missing file name".to_string()); };
+                let goal = match args.remainder().trim() {
+                    "" => None,
+                    goal => Some(self.parser.parse(goal)?),
+                };
+                let output = self.export_tptp(goal.as_ref())?;
+                std::fs::write(file_name, output).map_err(|err| err.to_string())?;
+            }
+            "retract" => {
+                let Some(id) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                let removed: Vec<String> = self.record(|sys| sys.retract(id))?.iter().map(|id| id.to_string()).collect();
+                println!("retracted {}", removed.join(", "));
+            }
+            "rename" => {
+                let Some(id) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                let Some(old) = args.next() else { return Err("missing variable".to_string()); };
+                let Some(new) = args.next() else { return Err("missing variable".to_string()); };
+                let new_id = self.record(|sys| sys.rename(id, old, new))?;
+                self.print_proof(new_id);
+            }
+            "induct" => {
+                let Some(var) = args.next() else { return Err("missing variable".to_string()); };
+                let Some(id_base) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id_base: usize = id_base.parse().map_err(|_| format!("invalid proof ID: {}", id_base))?;
+                let Some(id_step) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id_step: usize = id_step.parse().map_err(|_| format!("invalid proof ID: {}", id_step))?;
+                let new_id = self.record(|sys| sys.induct(var, id_base, id_step))?;
                 self.print_proof(new_id);
             }
+            "complete" => {
+                let mut segments = args.remainder().split(';');
+                let precedence: Vec<String> = segments.next().unwrap()
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect();
+                let mut equations = Vec::new();
+                for segment in segments {
+                    let form = self.parser.parse(segment)?;
+                    let map = self.parser.pattern_match(form.subtree_root(), "@a = @b")?;
+                    equations.push((map["a"].clone(), map["b"].clone()));
+                }
+                let lpo = completion::Lpo::new(&precedence);
+                self.rules = completion::complete(&equations, &lpo, self.parser.symdb(), completion::MAX_RULES)?;
+                for (i, rule) in self.rules.iter().enumerate() {
+                    println!("{}: {}", i, rewrite::rule_to_string(rule, self.parser.symdb()));
+                }
+            }
+            "rewrite" => {
+                let rem = args.remainder().trim_start();
+                let (strategy, rem) = if let Some(rem) = rem.strip_prefix("outermost ") {
+                    (rewrite::Strategy::Outermost, rem)
+                } else {
+                    (rewrite::Strategy::Innermost, rem.strip_prefix("innermost ").unwrap_or(rem))
+                };
+                let term = self.parser.parse(rem)?;
+                let trace = rewrite::normalize(&term, &self.rules, strategy, rewrite::MAX_STEPS);
+                print!("{}", rewrite::trace_to_string(&trace, &self.rules, self.parser.symdb()));
+                if !trace.normal {
+                    return Err(format!("no normal form within {} steps", rewrite::MAX_STEPS));
+                }
+            }
+            "lk" => {
+                let form = self.parser.parse(args.remainder())?;
+                let symdb = self.parser.symdb();
+                let deriv = lk::prove(&form, symdb, lk::MAX_INSTANCES)?;
+                print!("{}", lk::deriv_to_string(&deriv, symdb));
+            }
+            "resolve" => {
+                let mut forms = args.remainder().split(';')
+                    .map(|form| self.parser.parse(form))
+                    .collect::<Result<Vec<Tree<String>>, String>>()?;
+                let goal = forms.pop().unwrap();
+                let symdb = self.parser.symdb();
+                let refutation = resolution::prove(&forms, &goal, symdb, resolution::MAX_GIVEN)?;
+                print!("{}", resolution::refutation_to_string(&refutation, symdb));
+            }
             other => { return Err(format!("unknown command: {}", other)); }
         }
         Ok(())
     }
 
 }
+
+impl latex::Proofs for System {
+    fn symdb(&self) -> &SymDB {
+        self.parser.symdb()
+    }
+
+    fn num_proofs(&self) -> usize {
+        self.proofs.len()
+    }
+
+    fn derivation(&self, id: usize) -> Vec<usize> {
+        self.derivation(id)
+    }
+
+    fn line(&self, id: usize, templates: &HashMap<String, String>) -> latex::Line {
+        let symdb = self.parser.symdb();
+        let proof = &self.proofs[id];
+        let line = latex::Line::new(id, &proof.form, &self.rule_to_string(&proof.rule),
+            proof.rule.premises(), symdb, templates);
+        match &proof.rule {
+            Rule::Inst(_, subst) => line.with_subst(subst, symdb, templates),
+            _ => line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simultaneous_substitution() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let form = parser.parse("(x ∀ a = x) -> a = b").unwrap();
+        let subst = parse_subst(&mut parser, "a := b, b := a, x := c").unwrap();
+        let expected = parser.parse("(x ∀ b = x) -> b = a").unwrap();
+        assert_eq!(substitute_all(&form, &subst, parser.symdb()).unwrap(), expected);
+        let subst = parse_subst(&mut parser, "a := x").unwrap();
+        assert!(substitute_all(&form, &subst, parser.symdb()).is_err());
+    }
+
+    /// A system with the propositional axioms and `premises` as further axioms, and the IDs of `premises`.
+    fn system(premises: &[&str]) -> (System, Vec<usize>) {
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        let ids = premises.iter()
+            .map(|premise| {
+                let form = sys.parser.parse(premise).unwrap();
+                sys.axioms.push(form.clone());
+                sys.push(form, Rule::Axiom)
+            })
+            .collect();
+        (sys, ids)
+    }
+
+    /// Runs `cmd` and returns the ID of the proof it derived.
+    fn derive(sys: &mut System, cmd: &str) -> usize {
+        sys.command(cmd).unwrap();
+        sys.proofs.len() - 1
+    }
+
+    #[test]
+    fn equality() {
+        let (mut sys, ids) = system(&["a = b", "b = c", "(x ∀ a = x) -> a = b"]);
+        let (ab, bc, form) = (ids[0], ids[1], ids[2]);
+        let first = sys.proofs.len();
+        for cmd in [
+            format!("eq-sym {ab}"),
+            format!("eq-trans {ab} {bc}"),
+            format!("eq-subst {ab} {form} 1"),
+            format!("eq-subst {ab} {form}"),
+            "eq-refl a + b".to_string(),
+        ] {
+            sys.command(&cmd).unwrap();
+        }
+        let forms: Vec<String> = sys.proofs[first..].iter()
+            .map(|proof| sys.parser.symdb().ast_to_string_minimal(&proof.form))
+            .collect();
+        assert_eq!(forms, ["b = a", "a = c", "(x ∀ a = x) -> b = b", "(x ∀ b = x) -> b = b", "a + b = a + b"]);
+        assert_eq!(sys.proof_to_string(first + 2).unwrap(),
+            format!("{}: (x ∀ a = x) -> b = b [eq-subst {ab} {form} 1]", first + 2));
+        assert!(sys.command(&format!("eq-trans {ab} {ab}")).is_err());
+        assert!(sys.command(&format!("eq-subst {ab} {form} 2")).is_err());
+        let ax = derive(&mut sys, &format!("inst {ab} b := x"));
+        assert!(sys.command(&format!("eq-subst {ax} {form}")).is_err());
+        // Repeated positions are replaced once, and the step records them sorted.
+        let id = derive(&mut sys, &format!("eq-subst {ab} {form} 1 0 1"));
+        assert_eq!(sys.proof_to_string(id).unwrap(),
+            format!("{id}: (x ∀ b = x) -> b = b [eq-subst {ab} {form} 0 1]"));
+    }
+
+    #[test]
+    fn existential() {
+        let (mut sys, ids) = system(&[
+            "p = c", "x ∃ q = x", "q = y -> r = r", "q = y -> y = y", "x ∃ x = y", "y = y -> r = r",
+        ]);
+        let (pc, ex_q, q_r, q_y, ex_y, y_r) = (ids[0], ids[1], ids[2], ids[3], ids[4], ids[5]);
+        let id = derive(&mut sys, &format!("ex-intr {pc} x ∃ p = x"));
+        assert_eq!(sys.proof_to_string(id).unwrap(), format!("{id}: x ∃ p = x [ex-intr {pc} x ∃ p = x]"));
+        sys.command(&format!("ex-intr {pc} x ∃ x = c")).unwrap();
+        let id = derive(&mut sys, &format!("ex-elim {ex_q} {q_r}"));
+        assert_eq!(sys.proof_to_string(id).unwrap(), format!("{id}: r = r [ex-elim {ex_q} {q_r}]"));
+        assert!(sys.command(&format!("ex-intr {pc} x ∃ q = x")).is_err());
+        // The eigenvariable may occur free neither in the conclusion nor in `x ∃ A`.
+        assert!(sys.command(&format!("ex-elim {ex_q} {q_y}")).is_err());
+        assert!(sys.command(&format!("ex-elim {ex_y} {y_r}")).is_err());
+    }
+
+    #[test]
+    fn alpha_equivalence() {
+        let (mut sys, ids) = system(&["x ∀ p = x", "(y ∀ p = y) -> q = q", "x ∀ y ∀ x = y", "x ∀ x = y"]);
+        let (uq_p, imply, nested, free) = (ids[0], ids[1], ids[2], ids[3]);
+        assert!(!alpha_eq(sys.proofs[nested].form.subtree_root(), sys.proofs[free].form.subtree_root()));
+        let qq = derive(&mut sys, &format!("mp {uq_p} {imply}"));
+        // Captured by the inner binder, capturing the free `y`, not bound, and not a variable.
+        for cmd in [
+            format!("rename {nested} x y"),
+            format!("rename {free} x y"),
+            format!("rename {qq} y w"),
+            format!("rename {uq_p} x 0"),
+        ] {
+            assert!(sys.command(&cmd).is_err());
+        }
+        let id = derive(&mut sys, &format!("rename {nested} y z"));
+        assert_eq!(sys.proof_to_string(id).unwrap(), format!("{id}: x ∀ z ∀ x = z [rename {nested} y z]"));
+        assert_eq!(sys.duplicate(id), Some(nested));
+        let id = derive(&mut sys, &format!("rename {uq_p} x z"));
+        assert_eq!(sys.duplicate(id), Some(uq_p));
+    }
+
+    #[test]
+    fn renaming_substitution() {
+        let premises = ["x ∀ y = x", "x ∀ y = x & x1 = x1 & (y ∀ y = x)"];
+        let (mut sys, ids) = system(&premises);
+        assert!(sys.command(&format!("inst {} y := x", ids[0])).is_err());
+        sys.command("auto-rename on").unwrap();
+        let id0 = derive(&mut sys, &format!("inst {} y := x", ids[0]));
+        let id1 = derive(&mut sys, &format!("inst {} y := x", ids[1]));
+        let symdb = sys.parser.symdb();
+        assert_eq!(symdb.ast_to_string_minimal(&sys.proofs[id0].form), "x1 ∀ x = x1");
+        assert_eq!(symdb.ast_to_string_minimal(&sys.proofs[id1].form), "x2 ∀ x = x2 & x1 = x1 & (y ∀ y = x2)");
+        let file_name = std::env::temp_dir().join("logic_renaming_substitution.txt");
+        let file_name = file_name.to_str().unwrap();
+        sys.save(file_name).unwrap();
+        let (mut loaded, _) = system(&premises);
+        loaded.load(file_name).unwrap();
+        assert_eq!(loaded.proofs.len(), sys.proofs.len());
+        assert!(!loaded.auto_rename);
+    }
+
+    #[test]
+    fn tptp_constants() {
+        let file_name = std::env::temp_dir().join("logic_tptp_constants.p");
+        let file_name = file_name.to_str().unwrap();
+        std::fs::write(file_name, "fof(ax, axiom, p). fof(eq, axiom, a = b). fof(g, conjecture, a = c).").unwrap();
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        sys.command(&format!("tptp-import {}", file_name)).unwrap();
+        std::fs::remove_file(file_name).unwrap();
+        // `a` and `b` are variables of the axioms, so the constants are renamed.
+        assert_eq!(sys.proof_to_string(4).unwrap(), "4: a1 = b1 [axiom]");
+        for cmd in ["inst 3 p := F", "inst 4 a1 := c, b1 := d", "uq-intr 4 a1"] {
+            assert!(sys.command(cmd).is_err());
+        }
+        sys.command("inst 0 a := p").unwrap();
+        let exported = sys.export_tptp(None).unwrap();
+        assert!(exported.contains("fof(ax3, axiom, p).\nfof(ax4, axiom, (a1 = b1)).\n"));
+        sys.command("undo").unwrap();
+        sys.command("undo").unwrap();
+        assert!(sys.is_variable("a1"));
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        let n = sys.proofs.len();
+        sys.command("eq-refl x").unwrap();
+        sys.command("uq-intr 0 x").unwrap();
+        assert!(sys.command("mp 0 0").is_err());
+        sys.command("show").unwrap();
+        sys.command("undo").unwrap();
+        assert_eq!(sys.proofs.len(), n + 1);
+        sys.command("undo").unwrap();
+        assert_eq!(sys.proofs.len(), n);
+        assert!(sys.command("undo").is_err());
+        sys.command("redo").unwrap();
+        sys.command("redo").unwrap();
+        assert_eq!(sys.proofs[n + 1].rule, Rule::UqIntr(0, "x".to_string()));
+        sys.command(&format!("eq-sym {}", n)).unwrap();
+        sys.command(&format!("retract {}", n)).unwrap();
+        assert_eq!(sys.proofs.len(), n + 1);
+        assert_eq!(sys.proofs[n].rule, Rule::UqIntr(0, "x".to_string()));
+        sys.command("undo").unwrap();
+        assert_eq!(sys.proofs.len(), n + 3);
+    }
+
+    #[test]
+    fn induction() {
+        let mut sys = System::new("peano_ops.txt", "peano_axioms.txt").unwrap();
+        let (a, b, c) = ("0 + x = x", "S (0 + x) = S x", "0 + S x = S x");
+        for cmd in [
+            "inst 7 x := 0".to_string(),
+            "inst 5 x := 0 + x, y := x".to_string(),
+            "inst 8 x := 0, y := x".to_string(),
+            "inst 6 x := 0 + S x, y := S (0 + x), z := S x".to_string(),
+            "mp 14 15".to_string(),
+            format!("inst 0 a := {b} -> {c}, b := {a}"),
+            "mp 16 17".to_string(),
+            format!("inst 1 a := {a}, b := {b}, c := {c}"),
+            "mp 18 19".to_string(),
+            "mp 13 20".to_string(),
+        ] {
+            sys.command(&cmd).unwrap();
+        }
+        assert!(sys.command("induct x 21 21").is_err());
+        assert!(sys.command("induct y 12 21").is_err());
+        sys.command("induct x 12 21").unwrap();
+        assert_eq!(sys.proof_to_string(22).unwrap(), "22: x ∀ 0 + x = x [induct x 12 21]");
+    }
+}
@@ -0,0 +1,259 @@
+//! Automatic proofs of tautologies in the `->`/`F` axiom system,
+//! following Kalmár's proof of completeness.
+
+use tree::v3::{Tree, Subtree};
+use crate::proposition::{System, imply};
+use crate::semantics;
+use std::collections::HashMap;
+
+fn leaf(name: &str) -> Tree<String> {
+    Tree::new(name.to_string())
+}
+
+fn neg(form: Tree<String>) -> Tree<String> {
+    imply(form, leaf("F"))
+}
+
+/// Evaluates a formula built from `->`, `F` and variables.
+fn eval(form: Subtree<'_, String>, assign: &HashMap<String, bool>) -> bool {
+    semantics::eval(form, assign).expect("checked by `prove`")
+}
+
+/// Collects the lemmas `Prover::derive` uses for `form` under `assign`.
+fn lemmas_used(form: Subtree<'_, String>, assign: &HashMap<String, bool>, lemmas: &mut Vec<Lemma>) {
+    let mut args = form.children_root();
+    let lemma = match form.get_root().as_str() {
+        "F" => Lemma::FalseFalse,
+        "->" => {
+            let antecedent = args.next().unwrap();
+            let consequent = args.next().unwrap();
+            if eval(consequent, assign) {
+                lemmas_used(consequent, assign, lemmas);
+                return;
+            }
+            lemmas_used(antecedent, assign, lemmas);
+            if !eval(antecedent, assign) {
+                Lemma::FalseAntecedent
+            } else {
+                lemmas_used(consequent, assign, lemmas);
+                Lemma::TrueFalse
+            }
+        }
+        _ => return,
+    };
+    if !lemmas.contains(&lemma) {
+        lemmas.push(lemma);
+    }
+}
+
+/// Schematic lemmas proved once per call of `prove`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Lemma {
+    /// `F -> F`
+    FalseFalse,
+    /// `(p -> F) -> p -> q`
+    FalseAntecedent,
+    /// `p -> (q -> F) -> (p -> q) -> F`
+    TrueFalse,
+    /// `(p -> r) -> ((p -> F) -> r) -> r`
+    Cases,
+}
+
+struct Prover<'a> {
+    sys: &'a mut System,
+    lemmas: HashMap<Lemma, usize>,
+    /// The axiom `a -> b -> a` and its variables.
+    k: (usize, Vec<String>),
+    /// The axiom `((a -> F) -> F) -> a` and its variable.
+    dn: (usize, Vec<String>),
+    /// The current assignment and the IDs of the assumed literals.
+    assign: HashMap<String, bool>,
+    literals: HashMap<String, usize>,
+}
+
+impl<'a> Prover<'a> {
+    fn new(sys: &'a mut System) -> Result<Self, String> {
+        let k = sys.axiom_id("@a -> @b -> @a", &["a", "b"])?;
+        let dn = sys.axiom_id("((@a -> F) -> F) -> @a", &["a"])?;
+        Ok(Self { sys, lemmas: HashMap::new(), k, dn, assign: HashMap::new(), literals: HashMap::new() })
+    }
+
+    /// Derives `a` from `(a -> F) -> F` (`id`).
+    fn double_neg(&mut self, id: usize, form: Tree<String>) -> Result<usize, String> {
+        let dn = self.sys.inst(self.dn.0, &[(self.dn.1[0].clone(), form)])?;
+        self.sys.mp(id, dn)
+    }
+
+    fn lemma(&mut self, lemma: Lemma) -> Result<usize, String> {
+        if let Some(&id) = self.lemmas.get(&lemma) {
+            return Ok(id);
+        }
+        let (p, q, r) = (leaf("p"), leaf("q"), leaf("r"));
+        let sys = &mut *self.sys;
+        let id = match lemma {
+            Lemma::FalseFalse => {
+                let h = sys.assume(leaf("F"));
+                sys.discharge_at(h)?
+            }
+            Lemma::FalseAntecedent => {
+                let h_np = sys.assume(neg(p.clone()));
+                let h_p = sys.assume(p);
+                let f = sys.mp(h_p, h_np)?;
+                sys.assume(neg(q.clone()));
+                let nnq = sys.discharge_at(f)?;
+                let q = self.double_neg(nnq, q)?;
+                let sys = &mut *self.sys;
+                sys.discharge_at(q)?;
+                sys.discharge()?
+            }
+            Lemma::TrueFalse => {
+                let h_p = sys.assume(p.clone());
+                let h_nq = sys.assume(neg(q.clone()));
+                let h_pq = sys.assume(imply(p, q));
+                let q = sys.mp(h_p, h_pq)?;
+                sys.mp(q, h_nq)?;
+                sys.discharge()?;
+                sys.discharge()?;
+                sys.discharge()?
+            }
+            Lemma::Cases => {
+                let h_pr = sys.assume(imply(p.clone(), r.clone()));
+                let h_npr = sys.assume(imply(neg(p.clone()), r.clone()));
+                let h_nr = sys.assume(neg(r.clone()));
+                let h_p = sys.assume(p);
+                let r1 = sys.mp(h_p, h_pr)?;
+                let f = sys.mp(r1, h_nr)?;
+                let np = sys.discharge_at(f)?;
+                let r2 = sys.mp(np, h_npr)?;
+                sys.mp(r2, h_nr)?;
+                let nnr = sys.discharge()?;
+                let r = self.double_neg(nnr, r)?;
+                let sys = &mut *self.sys;
+                sys.discharge_at(r)?;
+                sys.discharge()?
+            }
+        };
+        self.lemmas.insert(lemma, id);
+        Ok(id)
+    }
+
+    /// Derives `form` if it is true under the current assignment and `form -> F` otherwise,
+    /// assuming the literals.
+    fn derive(&mut self, form: &Tree<String>) -> Result<usize, String> {
+        let mut args = form.children_root();
+        match form.get_root().unwrap().as_str() {
+            "F" => self.lemma(Lemma::FalseFalse),
+            "->" => {
+                let antecedent = form.clone_sub(args.next().unwrap());
+                let consequent = form.clone_sub(args.next().unwrap());
+                if eval(consequent.subtree_root(), &self.assign) {
+                    let id = self.derive(&consequent)?;
+                    let k = self.sys.inst(self.k.0, &[
+                        (self.k.1[0].clone(), consequent),
+                        (self.k.1[1].clone(), antecedent),
+                    ])?;
+                    self.sys.mp(id, k)
+                } else if !eval(antecedent.subtree_root(), &self.assign) {
+                    let id = self.derive(&antecedent)?;
+                    let lemma = self.lemma(Lemma::FalseAntecedent)?;
+                    let lemma = self.sys.inst(lemma, &[
+                        ("p".to_string(), antecedent),
+                        ("q".to_string(), consequent),
+                    ])?;
+                    self.sys.mp(id, lemma)
+                } else {
+                    let id_antec = self.derive(&antecedent)?;
+                    let id_conseq = self.derive(&consequent)?;
+                    let lemma = self.lemma(Lemma::TrueFalse)?;
+                    let lemma = self.sys.inst(lemma, &[
+                        ("p".to_string(), antecedent),
+                        ("q".to_string(), consequent),
+                    ])?;
+                    let step = self.sys.mp(id_antec, lemma)?;
+                    self.sys.mp(id_conseq, step)
+                }
+            }
+            var => Ok(self.literals[var]),
+        }
+    }
+
+    /// Derives `form` assuming the literals of `vars[..i]`, by case analysis on `vars[i..]`.
+    fn cases(&mut self, form: &Tree<String>, vars: &[String], i: usize) -> Result<usize, String> {
+        let Some(var) = vars.get(i) else {
+            return self.derive(form);
+        };
+        let mut imps = [0; 2];
+        for (imp, value) in imps.iter_mut().zip([true, false]) {
+            let literal = if value { leaf(var) } else { neg(leaf(var)) };
+            let hyp = self.sys.assume(literal);
+            self.assign.insert(var.clone(), value);
+            self.literals.insert(var.clone(), hyp);
+            let id = self.cases(form, vars, i + 1)?;
+            *imp = self.sys.discharge_at(id)?;
+        }
+        let lemma = self.lemma(Lemma::Cases)?;
+        let lemma = self.sys.inst(lemma, &[
+            ("p".to_string(), leaf(var)),
+            ("r".to_string(), form.clone()),
+        ])?;
+        let step = self.sys.mp(imps[0], lemma)?;
+        self.sys.mp(imps[1], step)
+    }
+}
+
+/// Appends a derivation of the tautology `form` to the proofs of `sys`
+/// and returns the ID of `form`.
+pub fn prove(sys: &mut System, form: &Tree<String>) -> Result<usize, String> {
+    for id in form.ids() {
+        let node = form.get_node(id).unwrap();
+        let value = node.value();
+        if value == "->" && node.children_len() == 2 || value == "F" && node.is_leaf() {
+            continue;
+        }
+        if !node.is_leaf() || !sys.symdb().is_variable(value) {
+            return Err(format!("unsupported symbol: {}", value));
+        }
+    }
+    let vars = semantics::variables(form.subtree_root(), sys.symdb());
+    if let Some(assign) = semantics::tautology(form.subtree_root(), sys.symdb())? {
+        let assign = semantics::assignment_to_string(&vars, &assign);
+        return Err(format!("not a tautology: false when {}", assign));
+    }
+    let mut lemmas = Vec::new();
+    if !vars.is_empty() {
+        lemmas.push(Lemma::Cases);
+    }
+    for bits in 0..1usize << vars.len() {
+        let assign: HashMap<String, bool> = vars.iter().enumerate()
+            .map(|(i, var)| (var.clone(), bits & 1 << i == 0))
+            .collect();
+        lemmas_used(form.subtree_root(), &assign, &mut lemmas);
+    }
+    let mut prover = Prover::new(sys)?;
+    // The lemmas are proved before any assumption, since discharging renumbers the proofs.
+    for lemma in lemmas {
+        prover.lemma(lemma)?;
+    }
+    prover.cases(form, &vars, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    #[test]
+    fn peirce() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        let form = parser.parse("((a -> b) -> a) -> a").unwrap();
+        let id = prove(&mut sys, &form).unwrap();
+        assert_eq!(sys.get(id).unwrap().form, form);
+
+        let form = parser.parse("(a -> b) -> a").unwrap();
+        let err = prove(&mut sys, &form).unwrap_err();
+        assert_eq!(err, "not a tautology: false when a = F, b = T");
+        let form = parser.parse("a & b -> a").unwrap();
+        assert!(prove(&mut sys, &form).is_err());
+    }
+}
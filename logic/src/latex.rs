@@ -0,0 +1,192 @@
+//! LaTeX output of derivations, as a numbered table or a `bussproofs` tree.
+
+use tree::v3::Tree;
+use parser::sym::SymDB;
+use crate::util::SplitWhitespace;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Loads the templates of operators, one `name template` per line.
+/// Empty lines and lines starting with `#` are skipped.
+pub fn load_templates(file_name: &str) -> Result<HashMap<String, String>, String> {
+    let reader = BufReader::new(File::open(file_name).map_err(|err| format!("{}: {}", file_name, err))?);
+    let mut templates = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, template)) = line.trim().split_once(char::is_whitespace) else {
+            return Err(format!("line {}: missing template", i + 1));
+        };
+        templates.insert(name.to_string(), template.trim().to_string());
+    }
+    Ok(templates)
+}
+
+/// A step of a derivation in LaTeX.
+pub struct Line {
+    pub id: usize,
+    pub form: String,
+    /// The name of the rule, such as `mp`.
+    pub rule: String,
+    /// Details of the rule other than the premises, such as a substitution.
+    pub detail: String,
+    pub premises: Vec<usize>,
+}
+
+impl Line {
+    /// Creates a line whose rule is the first word of `rule_str`, formatted as `rule_to_string` does.
+    pub fn new(id: usize, form: &Tree<String>, rule_str: &str, premises: Vec<usize>, symdb: &SymDB,
+    templates: &HashMap<String, String>) -> Self {
+        let rule = rule_str.split_whitespace().next().unwrap_or_default().to_string();
+        Self { id, form: symdb.ast_to_latex(form, templates), rule, detail: String::new(), premises }
+    }
+
+    /// Adds the substitution `[a := f, ...]` as the detail.
+    pub fn with_subst(mut self, subst: &[(String, Tree<String>)], symdb: &SymDB, templates: &HashMap<String, String>) -> Self {
+        let subst: Vec<String> = subst.iter()
+            .map(|(var, replace)| format!("{} := {}", var, symdb.ast_to_latex(replace, templates)))
+            .collect();
+        self.detail = format!("[{}]", subst.join(", "));
+        self
+    }
+
+    fn label(&self, with_premises: bool) -> String {
+        let mut label = format!(r"\text{{{}}}", self.rule);
+        if with_premises && !self.premises.is_empty() {
+            let premises: Vec<String> = self.premises.iter().map(|id| id.to_string()).collect();
+            label += &format!(r"\ {}", premises.join(", "));
+        }
+        if !self.detail.is_empty() {
+            label += &format!(r"\ {}", self.detail);
+        }
+        label
+    }
+}
+
+/// Proofs that can be written as LaTeX derivations.
+pub trait Proofs {
+    fn symdb(&self) -> &SymDB;
+    fn num_proofs(&self) -> usize;
+    /// IDs of all proofs `id` depends on, including `id` itself, in ascending order.
+    fn derivation(&self, id: usize) -> Vec<usize>;
+    fn line(&self, id: usize, templates: &HashMap<String, String>) -> Line;
+}
+
+/// The proofs `ids` as lines of a LaTeX derivation.
+pub fn lines(proofs: &impl Proofs, ids: &[usize], templates: &HashMap<String, String>) -> Vec<Line> {
+    ids.iter().map(|&id| proofs.line(id, templates)).collect()
+}
+
+/// Runs `latex [table|tree] [ID] [filename]` with the templates in `template_file`.
+/// The second argument is the ID if it is a number, and the file name otherwise.
+pub fn command(proofs: &impl Proofs, args: &mut SplitWhitespace, template_file: &str) -> Result<(), String> {
+    let format = args.next().unwrap_or("table");
+    let mut file_name = args.next();
+    let id = file_name.and_then(|arg| arg.parse::<usize>().ok());
+    if id.is_some() {
+        file_name = args.next();
+    }
+    if id.is_some_and(|id| id >= proofs.num_proofs()) {
+        return Err(format!("there is no proof with ID {}", id.unwrap()));
+    }
+    let ids: Vec<usize> = match id {
+        Some(id) => proofs.derivation(id),
+        None => (0..proofs.num_proofs()).collect(),
+    };
+    let templates = load_templates(template_file)?;
+    let lines = lines(proofs, &ids, &templates);
+    let output = match format {
+        "table" => table(&lines),
+        "tree" => bussproofs(&lines, id.ok_or_else(|| "missing proof ID".to_string())?)?,
+        other => return Err(format!("expected 'table' or 'tree': {}", other)),
+    };
+    match file_name {
+        Some(file_name) => std::fs::write(file_name, output).map_err(|err| err.to_string()),
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
+
+/// Formats the lines as a numbered table in an `array` environment.
+pub fn table(lines: &[Line]) -> String {
+    let mut s = "\\begin{array}{rll}\n".to_string();
+    for line in lines {
+        s += &format!("{}. & {} & {} \\\\\n", line.id, line.form, line.label(true));
+    }
+    s + "\\end{array}\n"
+}
+
+/// Formats the derivation of the line `id` as a `prooftree` of the `bussproofs` package.
+/// Premises used more than once are written out each time.
+pub fn bussproofs(lines: &[Line], id: usize) -> Result<String, String> {
+    fn write(lines: &[Line], id: usize, s: &mut String) -> Result<(), String> {
+        let Some(line) = lines.iter().find(|line| line.id == id) else {
+            return Err(format!("{} is not in the derivation", id));
+        };
+        if line.rule == "axiom" {
+            *s += &format!("\\AxiomC{{${}$}}\n", line.form);
+            return Ok(());
+        }
+        for &premise in line.premises.iter() {
+            write(lines, premise, s)?;
+        }
+        if line.premises.is_empty() {
+            *s += "\\AxiomC{}\n";
+        }
+        let inference = match line.premises.len() {
+            0 | 1 => "UnaryInfC",
+            2 => "BinaryInfC",
+            3 => "TrinaryInfC",
+            n => return Err(format!("a rule with {} premises", n)),
+        };
+        *s += &format!("\\RightLabel{{\\scriptsize ${}$}}\n\\{}{{${}$}}\n", line.label(false), inference, line.form);
+        Ok(())
+    }
+    let mut s = "\\begin{prooftree}\n".to_string();
+    write(lines, id, &mut s)?;
+    Ok(s + "\\end{prooftree}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+    use crate::proposition::System;
+
+    #[test]
+    fn derivation() {
+        let templates = load_templates("latex.txt").unwrap();
+        let mut parser = Parser::new("peano_ops.txt").unwrap();
+        let form = parser.parse("x ∀ (y ∃ S x = y) & ! (a_1 | F) -> x ^ (y + 1) = 0").unwrap();
+        assert_eq!(parser.symdb().ast_to_latex(&form, &templates),
+            r"\forall x\, (\exists y\, \mathrm{S}\,x = y) \land \lnot (a\_1 \lor \bot) \to x^{(y + 1)} = 0");
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        for cmd in ["D 0 1", "inst 3 b := F"] {
+            sys.command(cmd).unwrap();
+        }
+        let lines = lines(&sys, &sys.derivation(4), &templates);
+        assert_eq!(table(&lines).lines().nth(4),
+            Some(r"4. & (a \to \bot) \to a \to a & \text{inst}\ 3\ [b := \bot] \\"));
+        let tree = bussproofs(&lines, 4).unwrap();
+        assert_eq!(tree.lines().collect::<Vec<_>>(), [
+            r"\begin{prooftree}",
+            r"\AxiomC{$a \to b \to a$}",
+            r"\AxiomC{$(a \to b \to c) \to (a \to b) \to a \to c$}",
+            r"\RightLabel{\scriptsize $\text{D}$}",
+            r"\BinaryInfC{$(a \to b) \to a \to a$}",
+            r"\RightLabel{\scriptsize $\text{inst}\ [b := \bot]$}",
+            r"\UnaryInfC{$(a \to \bot) \to a \to a$}",
+            r"\end{prooftree}",
+        ]);
+        let file_name = std::env::temp_dir().join("logic_latex_table.tex");
+        let file_name = file_name.to_str().unwrap();
+        command(&sys, &mut SplitWhitespace::from(format!("table {}", file_name).as_str()), "latex.txt").unwrap();
+        assert_eq!(std::fs::read_to_string(file_name).unwrap().lines().count(), 7);
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
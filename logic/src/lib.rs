@@ -1,3 +1,15 @@
 pub mod proposition;
 pub mod fol;
-pub mod util;
\ No newline at end of file
+pub mod nd;
+pub mod lk;
+pub mod resolution;
+pub mod rewrite;
+pub mod completion;
+pub mod util;
+pub mod kalmar;
+pub mod semantics;
+pub mod sat;
+pub mod dimacs;
+pub mod metamath;
+pub mod latex;
+pub mod tptp;
\ No newline at end of file
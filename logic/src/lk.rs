@@ -0,0 +1,370 @@
+//! Backward proof search in the sequent calculus LK.
+//!
+//! The search uses the invertible rules of G3c, where `∀L` and `∃R` keep their principal
+//! formula, so only the terms for `∀L` and `∃R` are chosen. The number of those choices
+//! in a branch is bounded and the bound raised by iterative deepening, which makes the
+//! search complete as the bound grows. The proofs are cut-free.
+
+use tree::v3::{Tree, Subtree};
+use parser::sym::SymDB;
+use crate::fol;
+use std::cell::Cell;
+use std::collections::HashSet;
+
+/// The default bound on the number of `∀L` and `∃R` steps in a branch.
+pub const MAX_INSTANCES: usize = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequent {
+    pub ante: Vec<Tree<String>>,
+    pub succ: Vec<Tree<String>>,
+}
+
+impl Sequent {
+    /// Returns this sequent with the formulas of `ante` and `succ` added unless already present.
+    fn add(&self, ante: &[Tree<String>], succ: &[Tree<String>]) -> Self {
+        let mut seq = self.clone();
+        for form in ante {
+            if !seq.ante.contains(form) {
+                seq.ante.push(form.clone());
+            }
+        }
+        for form in succ {
+            if !seq.succ.contains(form) {
+                seq.succ.push(form.clone());
+            }
+        }
+        seq
+    }
+
+    fn forms(&self) -> impl Iterator<Item = &Tree<String>> {
+        self.ante.iter().chain(self.succ.iter())
+    }
+}
+
+/// A proof of `sequent` by `rule` from the proofs of its premises.
+#[derive(Debug, Clone)]
+pub struct Deriv {
+    pub sequent: Sequent,
+    pub rule: String,
+    pub premises: Vec<Deriv>,
+}
+
+fn is_connective(value: &str) -> bool {
+    matches!(value, "T" | "F" | "!" | "&" | "|" | "->" | "∀" | "∃")
+}
+
+fn operands(form: &Tree<String>) -> Vec<Tree<String>> {
+    form.children_root().map(|id| form.clone_sub(id)).collect()
+}
+
+fn leaf(name: &str) -> Tree<String> {
+    Tree::new(name.to_string())
+}
+
+/// Returns `base` followed by the first number that makes it unused.
+fn fresh(base: &str, used: &HashSet<String>) -> String {
+    (0..).map(|i| format!("{}{}", base, i))
+        .find(|name| !used.contains(name))
+        .unwrap()
+}
+
+/// Appends the free variables of `form` to `vars` in order of appearance.
+fn free_variables(form: Subtree<'_, String>, symdb: &SymDB, bound: &mut Vec<String>, vars: &mut Vec<String>) {
+    let value = form.get_root();
+    let mut args = form.children_root();
+    if value == "∀" || value == "∃" {
+        bound.push(args.next().unwrap().get_root().clone());
+        free_variables(args.next().unwrap(), symdb, bound, vars);
+        bound.pop();
+        return;
+    }
+    if form.is_leaf() && symdb.is_variable(value) && !bound.contains(value) && !vars.contains(value) {
+        vars.push(value.clone());
+    }
+    for arg in args {
+        free_variables(arg, symdb, bound, vars);
+    }
+}
+
+/// Appends the function symbols in the terms of `form` to `funcs` with their arities.
+fn functions(form: Subtree<'_, String>, in_term: bool, funcs: &mut Vec<(String, usize)>) {
+    let value = form.get_root();
+    let mut args = form.children_root();
+    if value == "∀" || value == "∃" {
+        args.next();
+    } else if in_term && !form.is_leaf() && !funcs.iter().any(|(name, _)| name == value) {
+        funcs.push((value.clone(), form.children_len()));
+    }
+    let in_term = in_term || !is_connective(value);
+    for arg in args {
+        functions(arg, in_term, funcs);
+    }
+}
+
+/// Renames the bound variables of `form` that are also in `free`.
+fn rectify(form: Subtree<'_, String>, free: &[String], used: &mut HashSet<String>, symdb: &SymDB) -> Tree<String> {
+    let value = form.get_root();
+    let mut args = form.children_root();
+    let mut tree = leaf(value);
+    if value == "∀" || value == "∃" {
+        let binder = args.next().unwrap().get_root().clone();
+        let body = rectify(args.next().unwrap(), free, used, symdb);
+        if !free.contains(&binder) {
+            tree.push(tree.root_id(), binder);
+            tree.push_tree(tree.root_id(), body);
+            return tree;
+        }
+        let var = fresh(&binder, used);
+        used.insert(var.clone());
+        let body = fol::substitute(&body, &binder, leaf(&var).subtree_root(), symdb)
+            .expect("a fresh variable is never captured");
+        tree.push(tree.root_id(), var);
+        tree.push_tree(tree.root_id(), body);
+        return tree;
+    }
+    for arg in args {
+        tree.push_tree(tree.root_id(), rectify(arg, free, used, symdb));
+    }
+    tree
+}
+
+struct Search<'a> {
+    symdb: &'a SymDB,
+    /// Whether a branch ran out of `∀L` and `∃R` steps.
+    exhausted: Cell<bool>,
+}
+
+impl Search<'_> {
+    fn used_names(&self, seq: &Sequent) -> HashSet<String> {
+        seq.forms()
+            .flat_map(|form| form.ids().map(move |id| form.get(id).unwrap().clone()))
+            .collect()
+    }
+
+    /// Returns the body of the quantified `form` with `term` in place of the bound variable.
+    fn instance(&self, form: &Tree<String>, term: &Tree<String>) -> Option<Tree<String>> {
+        let args = operands(form);
+        fol::substitute(&args[1], args[0].get_root().unwrap(), term.subtree_root(), self.symdb).ok()
+    }
+
+    /// Returns the terms built from the free variables of `seq` and the function symbols
+    /// in it, nested at most `depth` times, and whether there are function symbols.
+    fn terms(&self, seq: &Sequent, depth: usize) -> (Vec<Tree<String>>, bool) {
+        let mut vars = Vec::new();
+        let mut funcs = Vec::new();
+        for form in seq.forms() {
+            free_variables(form.subtree_root(), self.symdb, &mut Vec::new(), &mut vars);
+            functions(form.subtree_root(), false, &mut funcs);
+        }
+        if vars.is_empty() {
+            vars.push(fresh("c", &self.used_names(seq)));
+        }
+        let mut terms: Vec<Tree<String>> = vars.iter().map(|var| leaf(var)).collect();
+        for _ in 0..depth {
+            let mut new_terms = Vec::new();
+            for (func, arity) in funcs.iter() {
+                let mut tuples: Vec<Vec<&Tree<String>>> = vec![Vec::new()];
+                for _ in 0..*arity {
+                    tuples = tuples.into_iter()
+                        .flat_map(|tuple| terms.iter().map(move |term| {
+                            let mut tuple = tuple.clone();
+                            tuple.push(term);
+                            tuple
+                        }))
+                        .collect();
+                }
+                for tuple in tuples {
+                    let mut term = leaf(func);
+                    for arg in tuple {
+                        term.push_tree(term.root_id(), arg.clone());
+                    }
+                    if !terms.contains(&term) && !new_terms.contains(&term) {
+                        new_terms.push(term);
+                    }
+                }
+            }
+            terms.extend(new_terms);
+        }
+        (terms, !funcs.is_empty())
+    }
+
+    /// Applies an invertible rule to `seq` if one applies.
+    fn invertible(&self, seq: &Sequent) -> Option<(String, Vec<Sequent>)> {
+        for (i, form) in seq.ante.iter().enumerate() {
+            let mut rest = seq.clone();
+            rest.ante.remove(i);
+            let args = operands(form);
+            let result = match form.get_root().unwrap().as_str() {
+                "&" => ("&L".to_string(), vec![rest.add(&args, &[])]),
+                "|" => ("|L".to_string(), vec![rest.add(&args[..1], &[]), rest.add(&args[1..], &[])]),
+                "->" => ("->L".to_string(), vec![rest.add(&[], &args[..1]), rest.add(&args[1..], &[])]),
+                "!" => ("!L".to_string(), vec![rest.add(&[], &args)]),
+                "∃" => {
+                    let var = fresh(args[0].get_root().unwrap(), &self.used_names(seq));
+                    let body = self.instance(form, &leaf(&var)).unwrap();
+                    (format!("∃L {}", var), vec![rest.add(&[body], &[])])
+                }
+                _ => continue,
+            };
+            return Some(result);
+        }
+        for (i, form) in seq.succ.iter().enumerate() {
+            let mut rest = seq.clone();
+            rest.succ.remove(i);
+            let args = operands(form);
+            let result = match form.get_root().unwrap().as_str() {
+                "&" => ("&R".to_string(), vec![rest.add(&[], &args[..1]), rest.add(&[], &args[1..])]),
+                "|" => ("|R".to_string(), vec![rest.add(&[], &args)]),
+                "->" => ("->R".to_string(), vec![rest.add(&args[..1], &args[1..])]),
+                "!" => ("!R".to_string(), vec![rest.add(&args, &[])]),
+                "∀" => {
+                    let var = fresh(args[0].get_root().unwrap(), &self.used_names(seq));
+                    let body = self.instance(form, &leaf(&var)).unwrap();
+                    (format!("∀R {}", var), vec![rest.add(&[], &[body])])
+                }
+                _ => continue,
+            };
+            return Some(result);
+        }
+        None
+    }
+
+    /// Searches for a proof of `seq` with at most `budget` steps of `∀L` and `∃R` in each branch.
+    /// `instances` are the formulas those steps added in the branch so far,
+    /// with `true` for the antecedent.
+    fn search(&self, seq: Sequent, budget: usize, instances: &mut Vec<(bool, Tree<String>)>) -> Option<Deriv> {
+        let is_const = |form: &Tree<String>, name: &str| form.get_root().unwrap() == name;
+        let closed = seq.ante.iter().any(|form| is_const(form, "F") || seq.succ.contains(form))
+            || seq.succ.iter().any(|form| is_const(form, "T"));
+        if closed {
+            return Some(Deriv { sequent: seq, rule: "axiom".to_string(), premises: Vec::new() });
+        }
+        if let Some((rule, premises)) = self.invertible(&seq) {
+            let premises = premises.into_iter()
+                .map(|premise| self.search(premise, budget, instances))
+                .collect::<Option<Vec<Deriv>>>()?;
+            return Some(Deriv { sequent: seq, rule, premises });
+        }
+        let gammas: Vec<(bool, &Tree<String>)> = seq.ante.iter()
+            .filter(|form| is_const(form, "∀"))
+            .map(|form| (true, form))
+            .chain(seq.succ.iter().filter(|form| is_const(form, "∃")).map(|form| (false, form)))
+            .collect();
+        if gammas.is_empty() {
+            return None;
+        }
+        if budget == 0 {
+            self.exhausted.set(true);
+            return None;
+        }
+        let (terms, deeper) = self.terms(&seq, budget / 3);
+        if deeper {
+            self.exhausted.set(true);
+        }
+        for term in terms {
+            for &(left, form) in gammas.iter() {
+                let Some(body) = self.instance(form, &term) else { continue; };
+                let instance = (left, body);
+                if instances.contains(&instance) {
+                    continue;
+                }
+                let body = instance.1.clone();
+                let term = self.symdb.ast_to_string_minimal(&term);
+                let (premise, rule) = if left {
+                    (seq.add(&[body], &[]), format!("∀L {}", term))
+                } else {
+                    (seq.add(&[], &[body]), format!("∃R {}", term))
+                };
+                instances.push(instance);
+                let deriv = self.search(premise, budget - 1, instances);
+                instances.pop();
+                if let Some(deriv) = deriv {
+                    return Some(Deriv { sequent: seq, rule, premises: vec![deriv] });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Searches for a cut-free proof of `⊢ form` with at most `limit` steps of `∀L` and `∃R`
+/// in each branch.
+pub fn prove(form: &Tree<String>, symdb: &SymDB, limit: usize) -> Result<Deriv, String> {
+    for id in form.ids() {
+        let value = form.get(id).unwrap();
+        if value == "∀" || value == "∃" {
+            let binder = form.children(id).next().unwrap();
+            let node = form.get_node(binder).unwrap();
+            if !node.is_leaf() || !symdb.is_variable(node.value()) {
+                return Err(format!("invalid variable name: {}", symdb.ast_to_string_minimal(&form.clone_sub(binder))));
+            }
+        }
+    }
+    let mut free = Vec::new();
+    free_variables(form.subtree_root(), symdb, &mut Vec::new(), &mut free);
+    let mut used = form.ids().map(|id| form.get(id).unwrap().clone()).collect();
+    let form = rectify(form.subtree_root(), &free, &mut used, symdb);
+    let goal = Sequent { ante: Vec::new(), succ: vec![form] };
+    let search = Search { symdb, exhausted: Cell::new(false) };
+    for budget in 0..=limit {
+        search.exhausted.set(false);
+        if let Some(deriv) = search.search(goal.clone(), budget, &mut Vec::new()) {
+            return Ok(deriv);
+        }
+        if !search.exhausted.get() {
+            return Err("not provable".to_string());
+        }
+    }
+    Err(format!("no proof found with {} instances of quantifiers in a branch", limit))
+}
+
+/// Formats a sequent as `A, B ⊢ C`.
+pub fn sequent_to_string(seq: &Sequent, symdb: &SymDB) -> String {
+    let join = |forms: &[Tree<String>]| forms.iter()
+        .map(|form| symdb.ast_to_string_minimal(form))
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!("{} ⊢ {}", join(&seq.ante), join(&seq.succ)).trim().to_string()
+}
+
+/// Formats a proof with the conclusion first and each premise indented below its conclusion.
+pub fn deriv_to_string(deriv: &Deriv, symdb: &SymDB) -> String {
+    let mut s = String::new();
+    let mut stack = vec![(deriv, 0)];
+    while let Some((deriv, depth)) = stack.pop() {
+        s += &format!("{}{} [{}]\n", "  ".repeat(depth), sequent_to_string(&deriv.sequent, symdb), deriv.rule);
+        stack.extend(deriv.premises.iter().rev().map(|premise| (premise, depth + 1)));
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    #[test]
+    fn search() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let form = parser.parse("((a -> b) -> a) -> a").unwrap();
+        let deriv = prove(&form, parser.symdb(), MAX_INSTANCES).unwrap();
+        assert_eq!(deriv_to_string(&deriv, parser.symdb()), "\
+⊢ ((a -> b) -> a) -> a [->R]
+  (a -> b) -> a ⊢ a [->L]
+    ⊢ a, a -> b [->R]
+      a ⊢ a, b [axiom]
+    a ⊢ a [axiom]
+");
+        let form = parser.parse("a | b -> a").unwrap();
+        assert_eq!(prove(&form, parser.symdb(), MAX_INSTANCES).unwrap_err(), "not provable");
+
+        let form = parser.parse("x ∃ (x = c -> (y ∀ y = c))").unwrap();
+        let deriv = prove(&form, parser.symdb(), MAX_INSTANCES).unwrap();
+        assert_eq!(deriv.rule, "∃R c");
+        let form = parser.parse("(x ∀ p = x) -> (y ∃ p = y)").unwrap();
+        assert!(prove(&form, parser.symdb(), MAX_INSTANCES).is_ok());
+        let form = parser.parse("(y ∃ x = y) -> x ∃ x = x").unwrap();
+        assert!(prove(&form, parser.symdb(), 2).is_err());
+    }
+}
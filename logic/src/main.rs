@@ -1,34 +1,149 @@
-use std::{io, env};
-use logic::proposition::System;
+use std::{io, env, fs};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use logic::{proposition, fol, nd, util};
 
-fn main() -> Result<(), &'static str> {
+enum Logic {
+    Prop(proposition::System),
+    Fol(fol::System),
+    Nd(nd::System),
+}
+
+impl Logic {
+    /// Creates the system for the logic `name`,
+    /// with the default operator and axiom files unless `op_file` and `axiom_file` are given.
+    fn new(name: &str, op_file: Option<&str>, axiom_file: Option<&str>) -> Result<Self, String> {
+        let ops = op_file.unwrap_or(if name == "peano" { "peano_ops.txt" } else { "ops.txt" });
+        match name {
+            "prop" => Ok(Self::Prop(proposition::System::new(ops, axiom_file.unwrap_or("prop_axioms.txt"))?)),
+            "fol" => Ok(Self::Fol(fol::System::new(ops, axiom_file.unwrap_or("prop_axioms.txt"))?)),
+            "peano" => Ok(Self::Fol(fol::System::new(ops, axiom_file.unwrap_or("peano_axioms.txt"))?)),
+            "nd" if axiom_file.is_some() => Err("nd uses no axioms".to_string()),
+            "nd" => Ok(Self::Nd(nd::System::new(ops)?)),
+            other => Err(format!("unknown logic: {}", other)),
+        }
+    }
+
+    fn command(&mut self, s: &str) -> Result<(), String> {
+        self.command_in(s, &mut Vec::new())
+    }
+
+    /// Runs a command of the nested scripts `running`, outermost first.
+    fn command_in(&mut self, s: &str, running: &mut Vec<PathBuf>) -> Result<(), String> {
+        if let Some(("run", file_name)) = s.split_once(char::is_whitespace) {
+            return self.run(file_name.trim(), running);
+        }
+        match self {
+            Self::Prop(sys) => sys.command(s),
+            Self::Fol(sys) => sys.command(s),
+            Self::Nd(sys) => sys.command(s),
+        }
+    }
+
+    fn set_latex_templates(&mut self, file_name: &str) -> Result<(), String> {
+        match self {
+            Self::Prop(sys) => sys.set_latex_templates(file_name),
+            Self::Fol(sys) => sys.set_latex_templates(file_name),
+            Self::Nd(_) => return Err("nd has no LaTeX output".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Runs the script `file_name`, failing if it is already running, which would never end.
+    fn run(&mut self, file_name: &str, running: &mut Vec<PathBuf>) -> Result<(), String> {
+        let path = fs::canonicalize(file_name).map_err(|err| format!("{}: {}", file_name, err))?;
+        if running.contains(&path) {
+            return Err(format!("{} runs itself", file_name));
+        }
+        running.push(path);
+        let result = util::run_script(file_name, |line| self.command_in(line, running));
+        running.pop();
+        result
+    }
+}
+
+struct Options {
+    logic: String,
+    op_file: Option<String>,
+    axiom_file: Option<String>,
+    latex_file: Option<String>,
+    batch: Option<String>,
+}
+
+const USAGE: &str = "usage: logic [prop|fol|peano|nd] [--ops <file>] [--axioms <file>] [--latex <file>] [--batch <script>]";
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options { logic: "prop".to_string(), op_file: None, axiom_file: None, latex_file: None, batch: None };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--ops" => options.op_file = Some(value()?),
+            "--axioms" => options.axiom_file = Some(value()?),
+            "--latex" => options.latex_file = Some(value()?),
+            "--batch" => options.batch = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => options.logic = arg,
+        }
+    }
+    Ok(options)
+}
+
+fn main() -> ExitCode {
     
-    let mut arg_iter = env::args_os();
+    let mut arg_iter = env::args();
 
     assert!(arg_iter.next().is_some());
 
+    let options = match parse_options(arg_iter) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let logic = Logic::new(&options.logic, options.op_file.as_deref(), options.axiom_file.as_deref())
+        .and_then(|mut sys| match &options.latex_file {
+            Some(file_name) => sys.set_latex_templates(file_name).map(|_| sys),
+            None => Ok(sys),
+        });
+    let mut sys = match logic {
+        Ok(sys) => sys,
+        Err(err) => {
+            eprintln!("initialization error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Some(script) = options.batch {
+        return match sys.run(&script, &mut Vec::new()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
     let input = io::stdin();
     let mut buf = String::new();
-    let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
-
     loop {
-        if let Err(err) = input.read_line(&mut buf) {
-            println!("{}", err);
-            return Err("input error");
+        match input.read_line(&mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("input error: {}", err);
+                return ExitCode::FAILURE;
+            }
         }
-        println!("{} bytes read", buf.len());
         let trimmed = buf.trim();
         if trimmed == "exit" {
             break;
         }
-        match sys.command(trimmed) {
-            Err(err) => {
-                println!("Error: {:?}", err);
-            }
-            Ok(_) => {}
+        if let Err(err) = sys.command(trimmed) {
+            println!("Error: {:?}", err);
         }
         buf.clear();
     }
     println!("Exiting...");
-    Ok(())
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,254 @@
+//! Export of `proposition::System` proofs as a Metamath database.
+//!
+//! Every operator of the symbol database becomes a constant with a syntax axiom `w.[index]`,
+//! and every variable a `wff` variable with the floating hypothesis `wv.[name]`.
+//! Operators with operands in front of them are enclosed in parentheses.
+//! Axioms become `ax.[ID]` and derived formulas `p.[ID]`, proved from the earlier statements with `mp`.
+//! Steps depending on an open assumption are left out.
+
+use tree::v3::{Tree, Subtree};
+use parser::parser::substitute_tree;
+use parser::sym::SymDB;
+use crate::proposition::{self, Rule, System};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+
+const METAVARIABLES: [&str; 5] = ["ph", "ps", "ch", "th", "ta"];
+
+fn is_label(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn is_math_symbol(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_graphic() && c != '$')
+}
+
+/// Names and labels of the symbols of a database.
+struct Symbols<'a> {
+    symdb: &'a SymDB,
+    /// Constants with the labels of their syntax axioms: the operators, then the other symbols.
+    constants: Vec<(String, String)>,
+    /// Variables in the order of their floating hypotheses, starting with the metavariables.
+    variables: Vec<String>,
+    /// Variables of the syntax axioms and `mp`, not occurring in the formulas.
+    metavariables: Vec<String>,
+}
+
+impl<'a> Symbols<'a> {
+    fn new(symdb: &'a SymDB, forms: &[&Tree<String>]) -> Result<Self, String> {
+        let mut constants: Vec<(String, String)> = symdb.iter().enumerate()
+            .filter(|(_, sym)| is_math_symbol(&sym.name))
+            .map(|(i, sym)| (sym.name.clone(), format!("w.{}", i)))
+            .collect();
+        let mut vars = BTreeSet::new();
+        let mut others = BTreeSet::new();
+        for form in forms {
+            for id in form.ids() {
+                let value = form.get(id).unwrap();
+                if symdb.is_variable(value) {
+                    if !is_label(value) || value == "wff" {
+                        return Err(format!("{} cannot be a Metamath variable", value));
+                    }
+                    vars.insert(value.clone());
+                } else if !constants.iter().any(|(name, _)| name == value) {
+                    if !is_label(value) {
+                        return Err(format!("{} cannot be a Metamath constant", value));
+                    }
+                    others.insert(value.clone());
+                }
+            }
+        }
+        constants.extend(others.into_iter().map(|name| {
+            let label = format!("wc.{}", name);
+            (name, label)
+        }));
+        let arity = symdb.iter().map(|sym| sym.arity()).max().unwrap_or(0).max(2);
+        if arity > METAVARIABLES.len() {
+            return Err(format!("an operator with {} operands", arity));
+        }
+        let metavariables: Vec<String> = METAVARIABLES[..arity].iter()
+            .map(|&name| {
+                (0..).map(|i| if i == 0 { name.to_string() } else { format!("{}{}", name, i) })
+                    .find(|var| !vars.contains(var) && !constants.iter().any(|(name, _)| name == var))
+                    .unwrap()
+            })
+            .collect();
+        let variables = metavariables.iter().cloned().chain(vars).collect();
+        Ok(Self { symdb, constants, variables, metavariables })
+    }
+
+    /// The formula as a sequence of math symbols.
+    fn expression(&self, form: Subtree<String>) -> String {
+        let value = form.get_root();
+        if self.symdb.is_variable(value) {
+            return value.clone();
+        }
+        let front = self.symdb.get(value).layout.front;
+        let operands: Vec<String> = form.children_root().map(|operand| self.expression(operand)).collect();
+        let mut tokens: Vec<&str> = operands.iter().map(String::as_str).collect();
+        tokens.insert(front.min(tokens.len()), value);
+        if front > 0 {
+            format!("( {} )", tokens.join(" "))
+        } else {
+            tokens.join(" ")
+        }
+    }
+
+    /// The proof that the formula is a `wff`, in reverse Polish notation.
+    fn syntax_proof(&self, form: Subtree<String>, proof: &mut Vec<String>) {
+        let value = form.get_root();
+        if self.symdb.is_variable(value) {
+            return proof.push(format!("wv.{}", value));
+        }
+        for operand in form.children_root() {
+            self.syntax_proof(operand, proof);
+        }
+        let (_, label) = self.constants.iter().find(|(name, _)| name == value).unwrap();
+        proof.push(label.clone());
+    }
+
+    /// The proof steps referring to the statement `label` about `form`,
+    /// whose variables are replaced as in `subst`.
+    fn reference(&self, label: &str, form: &Tree<String>, subst: &[(String, Tree<String>)], proof: &mut Vec<String>) {
+        for var in self.variables.iter().filter(|&var| form.ids().any(|id| form.get(id).unwrap() == var)) {
+            match subst.iter().find(|(name, _)| name == var) {
+                Some((_, replace)) => self.syntax_proof(replace.subtree_root(), proof),
+                None => proof.push(format!("wv.{}", var)),
+            }
+        }
+        proof.push(label.to_string());
+    }
+}
+
+fn label(sys: &System, id: usize) -> String {
+    match sys.get(id).unwrap().rule {
+        Rule::Axiom => format!("ax.{}", id),
+        _ => format!("p.{}", id),
+    }
+}
+
+/// Writes the proofs of `sys` that do not depend on an open assumption as a Metamath database.
+pub fn write<W: Write>(sys: &System, mut writer: W) -> Result<(), String> {
+    let symdb = sys.symdb();
+    let proofs: Vec<&proposition::Proof> = (0..).map_while(|id| sys.get(id)).collect();
+    let mut exported = vec![true; proofs.len()];
+    for (id, proof) in proofs.iter().enumerate() {
+        exported[id] = proof.rule != Rule::Assume && proof.rule.premises().iter().all(|&premise| exported[premise]);
+    }
+    let mut forms: Vec<&Tree<String>> = Vec::new();
+    let mut d_substs = HashMap::new();
+    for (id, proof) in proofs.iter().enumerate().filter(|&(id, _)| exported[id]) {
+        forms.push(&proof.form);
+        match &proof.rule {
+            Rule::Inst(_, subst) => forms.extend(subst.iter().map(|(_, replace)| replace)),
+            Rule::D(..) => {
+                let substs = sys.d_substitutions(id)?;
+                d_substs.insert(id, substs);
+            }
+            _ => {}
+        }
+    }
+    for (subst_antec, subst_imply) in d_substs.values() {
+        forms.extend(subst_antec.iter().chain(subst_imply.iter()).map(|(_, replace)| replace));
+    }
+    let symbols = Symbols::new(symdb, &forms)?;
+    let [ph, ps, ..] = symbols.metavariables.as_slice() else { unreachable!() };
+    if symdb.get("->").arity() != 2 {
+        return Err("-> is not a binary operator".to_string());
+    }
+
+    let mut lines = vec!["$( Exported from the proofs of logic. $)".to_string(), String::new()];
+    let names: Vec<&str> = symbols.constants.iter().map(|(name, _)| name.as_str()).collect();
+    lines.push(format!("$c wff |- ( ) {} $.", names.join(" ")));
+    lines.push(format!("$v {} $.", symbols.variables.join(" ")));
+    for var in symbols.variables.iter() {
+        lines.push(format!("wv.{} $f wff {} $.", var, var));
+    }
+    for (name, label) in symbols.constants.iter() {
+        let operands: Vec<Tree<String>> = symbols.metavariables.iter()
+            .take(symdb.get(name).arity())
+            .map(|var| Tree::new(var.clone()))
+            .collect();
+        let mut form = Tree::new(name.to_string());
+        for operand in operands {
+            form.push_tree(form.root_id(), operand);
+        }
+        lines.push(format!("{} $a wff {} $.", label, symbols.expression(form.subtree_root())));
+    }
+    let ph_to_ps = proposition::imply(Tree::new(ph.clone()), Tree::new(ps.clone()));
+    lines.push(String::new());
+    lines.push("${".to_string());
+    lines.push(format!("  mp.min $e |- {} $.", ph));
+    lines.push(format!("  mp.maj $e |- {} $.", symbols.expression(ph_to_ps.subtree_root())));
+    lines.push(format!("  mp $a |- {} $.", ps));
+    lines.push("$}".to_string());
+    lines.push(String::new());
+
+    for (id, proof) in proofs.iter().enumerate() {
+        let statement = symbols.expression(proof.form.subtree_root());
+        if !exported[id] {
+            lines.push(format!("$( {} depends on an open assumption: {} $)", id, statement));
+            continue;
+        }
+        let mut steps = Vec::new();
+        match &proof.rule {
+            Rule::Axiom => {
+                lines.push(format!("ax.{} $a |- {} $.", id, statement));
+                continue;
+            }
+            Rule::Assume => unreachable!(),
+            Rule::Inst(id_inst, subst) => {
+                symbols.reference(&label(sys, *id_inst), &proofs[*id_inst].form, subst, &mut steps);
+            }
+            Rule::Mp(id_antec, id_imply) => {
+                let antecedent = &proofs[*id_antec].form;
+                symbols.syntax_proof(antecedent.subtree_root(), &mut steps);
+                symbols.syntax_proof(proof.form.subtree_root(), &mut steps);
+                symbols.reference(&label(sys, *id_antec), antecedent, &[], &mut steps);
+                symbols.reference(&label(sys, *id_imply), &proofs[*id_imply].form, &[], &mut steps);
+                steps.push("mp".to_string());
+            }
+            Rule::D(id_antec, id_imply) => {
+                let (subst_antec, subst_imply) = &d_substs[&id];
+                let antecedent = &proofs[*id_antec].form;
+                let subst_map: HashMap<String, Tree<String>> = subst_antec.iter().cloned().collect();
+                let instance = substitute_tree(antecedent.subtree_root(), &subst_map);
+                symbols.syntax_proof(instance.subtree_root(), &mut steps);
+                symbols.syntax_proof(proof.form.subtree_root(), &mut steps);
+                symbols.reference(&label(sys, *id_antec), antecedent, subst_antec, &mut steps);
+                symbols.reference(&label(sys, *id_imply), &proofs[*id_imply].form, subst_imply, &mut steps);
+                steps.push("mp".to_string());
+            }
+        }
+        lines.push(format!("p.{} $p |- {} $= {} $.", id, statement, steps.join(" ")));
+    }
+    for line in lines {
+        writeln!(writer, "{}", line).map_err(|err| err.to_string())?;
+    }
+    writer.flush().map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export() {
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        for cmd in ["D 0 1", "D 0 3", "assume q", "D 5 0", "inst 0 b := c"] {
+            sys.command(cmd).unwrap();
+        }
+        let mut buffer = Vec::new();
+        write(&sys, &mut buffer).unwrap();
+        let db = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = db.lines().collect();
+        assert!(lines.contains(&"$v ph ps a b c v1 $."));
+        assert!(lines.contains(&"w.13 $a wff ( ph -> ps ) $."));
+        assert!(lines.contains(&"ax.0 $a |- ( a -> ( b -> a ) ) $."));
+        assert!(lines.contains(&"p.4 $p |- ( a -> a ) $= wv.a wv.v1 wv.a w.13 w.13 wv.a wv.a w.13 \
+            wv.a wv.v1 ax.0 wv.a wv.v1 wv.a w.13 p.3 mp $."));
+        assert!(lines.contains(&"$( 5 depends on an open assumption: q $)"));
+        assert!(lines.contains(&"$( 6 depends on an open assumption: ( b -> q ) $)"));
+        assert_eq!(lines.last(), Some(&"p.7 $p |- ( a -> ( c -> a ) ) $= wv.a wv.c ax.0 $."));
+    }
+}
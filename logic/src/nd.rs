@@ -0,0 +1,620 @@
+//! Natural deduction in the style of Fitch.
+//!
+//! An assumption opens a box, which `end` closes. A step may use the lines whose boxes
+//! are all still open, and the introduction rules for `->`, `!` and the rules `or-elim`,
+//! `ex-elim` and `raa` use closed boxes, written `[assumption ID]-[last ID]`.
+
+use tree::v3::Tree;
+use parser::parser::Parser;
+use crate::fol;
+use crate::util::SplitWhitespace;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write, BufWriter};
+use std::io::{Error, ErrorKind};
+
+/// A closed box from its assumption to its last line.
+pub type Span = (usize, usize);
+
+/// The inference that produced a formula in `System::proofs`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Assume,
+    Copy(usize),
+    TrueIntr,
+    AndIntr(usize, usize),
+    AndElim1(usize),
+    AndElim2(usize),
+    OrIntr(usize),
+    OrElim(usize, Span, Span),
+    ImpIntr(Span),
+    ImpElim(usize, usize),
+    NegIntr(Span),
+    NegElim(usize, usize),
+    FalseElim(usize),
+    Raa(Span),
+    UqIntr(usize, String),
+    UqElim(usize, Tree<String>),
+    ExIntr(usize),
+    ExElim(usize, Span),
+}
+
+impl Rule {
+    /// IDs of the proofs this step was derived from, including every line of the boxes it uses.
+    pub fn premises(&self) -> Vec<usize> {
+        match *self {
+            Rule::Assume | Rule::TrueIntr => Vec::new(),
+            Rule::Copy(id) | Rule::AndElim1(id) | Rule::AndElim2(id) | Rule::OrIntr(id)
+            | Rule::FalseElim(id) | Rule::UqIntr(id, _) | Rule::UqElim(id, _) | Rule::ExIntr(id) => vec![id],
+            Rule::AndIntr(id1, id2) | Rule::ImpElim(id1, id2) | Rule::NegElim(id1, id2) => vec![id1, id2],
+            Rule::ImpIntr(span) | Rule::NegIntr(span) | Rule::Raa(span) => (span.0..=span.1).collect(),
+            Rule::OrElim(id, span1, span2) => std::iter::once(id)
+                .chain(span1.0..=span1.1)
+                .chain(span2.0..=span2.1)
+                .collect(),
+            Rule::ExElim(id, span) => std::iter::once(id).chain(span.0..=span.1).collect(),
+        }
+    }
+
+    /// Whether the conclusion must be given, since the premises do not determine it.
+    pub fn needs_conclusion(&self) -> bool {
+        matches!(self, Rule::Assume | Rule::OrIntr(_) | Rule::FalseElim(_) | Rule::ExIntr(_))
+    }
+}
+
+pub struct Proof {
+    pub form: Tree<String>,
+    pub rule: Rule,
+    /// The assumptions of the boxes containing this line, outermost first.
+    pub boxes: Vec<usize>,
+}
+
+pub struct System {
+    proofs: Vec<Proof>,
+    /// The assumptions of the open boxes, outermost first.
+    open: Vec<usize>,
+    parser: Parser,
+}
+
+fn span_to_string(span: Span) -> String {
+    format!("{}-{}", span.0, span.1)
+}
+
+fn binary(op: &str, lhs: Tree<String>, rhs: Tree<String>) -> Tree<String> {
+    let mut tree = Tree::new(op.to_string());
+    tree.push_tree(tree.root_id(), lhs);
+    tree.push_tree(tree.root_id(), rhs);
+    tree
+}
+
+impl System {
+
+    pub fn new(op_file: &str) -> Result<Self, String> {
+        let parser = Parser::new(op_file)?;
+        Ok(Self { proofs: Vec::new(), open: Vec::new(), parser })
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Proof> {
+        self.proofs.get(id)
+    }
+
+    fn push(&mut self, form: Tree<String>, rule: Rule) -> usize {
+        let id = self.proofs.len();
+        self.proofs.push(Proof { form, rule, boxes: self.open.clone() });
+        id
+    }
+
+    /// Returns the formula of `id` if the step can use it.
+    fn line(&self, id: usize) -> Result<Tree<String>, String> {
+        let Some(proof) = self.proofs.get(id) else {
+            return Err(format!("there is no proof with ID {}", id));
+        };
+        if !self.open.starts_with(&proof.boxes) {
+            return Err(format!("{} is inside a closed box", id));
+        }
+        Ok(proof.form.clone())
+    }
+
+    /// Returns the assumption and the last formula of the closed box `span`.
+    fn closed_box(&self, span: Span) -> Result<(Tree<String>, Tree<String>), String> {
+        let (first, last) = span;
+        let Some(proof) = self.proofs.get(first).filter(|proof| proof.rule == Rule::Assume) else {
+            return Err(format!("{} is not an assumption", first));
+        };
+        let (&assumption, outer) = proof.boxes.split_last().unwrap();
+        if !self.open.starts_with(outer) {
+            return Err(format!("{} is inside a closed box", first));
+        }
+        if self.open.contains(&assumption) {
+            return Err(format!("the box of {} is not closed", first));
+        }
+        match self.proofs.get(last) {
+            Some(proof_last) if last >= first && proof_last.boxes == proof.boxes =>
+                Ok((proof.form.clone(), proof_last.form.clone())),
+            _ => Err(format!("{} is not in the box of {}", last, first)),
+        }
+    }
+
+    /// Fails if `var` occurs free in an open assumption.
+    fn check_eigenvariable(&self, var: &str) -> Result<(), String> {
+        let symdb = self.parser.symdb();
+        for &id in self.open.iter() {
+            if fol::free_variables(self.proofs[id].form.subtree_root(), symdb).contains(var) {
+                return Err(format!("{} occurs free in the assumption {}", var, id));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn assume(&mut self, form: Tree<String>) -> usize {
+        let id = self.proofs.len();
+        self.open.push(id);
+        self.push(form, Rule::Assume)
+    }
+
+    /// Closes the innermost box and returns its span.
+    pub fn end(&mut self) -> Result<Span, String> {
+        let Some(first) = self.open.pop() else {
+            return Err("no open box".to_string());
+        };
+        let last = (first..self.proofs.len()).rev()
+            .find(|&id| self.proofs[id].boxes.len() == self.open.len() + 1)
+            .unwrap();
+        Ok((first, last))
+    }
+
+    pub fn copy(&mut self, id: usize) -> Result<usize, String> {
+        let form = self.line(id)?;
+        Ok(self.push(form, Rule::Copy(id)))
+    }
+
+    pub fn true_intr(&mut self) -> usize {
+        self.push(Tree::new("T".to_string()), Rule::TrueIntr)
+    }
+
+    pub fn and_intr(&mut self, id1: usize, id2: usize) -> Result<usize, String> {
+        let form = binary("&", self.line(id1)?, self.line(id2)?);
+        Ok(self.push(form, Rule::AndIntr(id1, id2)))
+    }
+
+    /// Derives the `n`th operand of the conjunction `id`.
+    pub fn and_elim(&mut self, id: usize, n: usize) -> Result<usize, String> {
+        let form = self.line(id)?;
+        let map = self.parser.pattern_match(form.subtree_root(), "@a & @b")?;
+        let (form, rule) = if n == 1 {
+            (map["a"].clone(), Rule::AndElim1(id))
+        } else {
+            (map["b"].clone(), Rule::AndElim2(id))
+        };
+        Ok(self.push(form, rule))
+    }
+
+    /// Derives the disjunction `form` from one of its operands (`id`).
+    pub fn or_intr(&mut self, id: usize, form: Tree<String>) -> Result<usize, String> {
+        let operand = self.line(id)?;
+        let map = self.parser.pattern_match(form.subtree_root(), "@a | @b")?;
+        if map["a"] != operand && map["b"] != operand {
+            return Err(format!("{} is not an operand", id));
+        }
+        Ok(self.push(form, Rule::OrIntr(id)))
+    }
+
+    pub fn or_elim(&mut self, id: usize, span1: Span, span2: Span) -> Result<usize, String> {
+        let form = self.line(id)?;
+        let map = self.parser.pattern_match(form.subtree_root(), "@a | @b")?;
+        let (assumption1, form1) = self.closed_box(span1)?;
+        let (assumption2, form2) = self.closed_box(span2)?;
+        if assumption1 != map["a"] || assumption2 != map["b"] {
+            return Err("a mismatched pattern '[a] | [b], [a] ... [c], [b] ... [c]'".to_string());
+        }
+        if form1 != form2 {
+            return Err("the boxes derive different formulas".to_string());
+        }
+        Ok(self.push(form1, Rule::OrElim(id, span1, span2)))
+    }
+
+    pub fn imp_intr(&mut self, span: Span) -> Result<usize, String> {
+        let (assumption, form) = self.closed_box(span)?;
+        Ok(self.push(binary("->", assumption, form), Rule::ImpIntr(span)))
+    }
+
+    pub fn imp_elim(&mut self, id_antec: usize, id_imply: usize) -> Result<usize, String> {
+        let antecedent = self.line(id_antec)?;
+        let form = self.line(id_imply)?;
+        let map = self.parser.pattern_match(form.subtree_root(), "@a -> @b")?;
+        if map["a"] != antecedent {
+            return Err("a mismatched pattern '[a], [a] -> [b]'".to_string());
+        }
+        Ok(self.push(map["b"].clone(), Rule::ImpElim(id_antec, id_imply)))
+    }
+
+    pub fn neg_intr(&mut self, span: Span) -> Result<usize, String> {
+        let (assumption, form) = self.closed_box(span)?;
+        if form.get_root().unwrap() != "F" {
+            return Err("a mismatched pattern '[a] ... F'".to_string());
+        }
+        let mut neg = Tree::new("!".to_string());
+        neg.push_tree(neg.root_id(), assumption);
+        Ok(self.push(neg, Rule::NegIntr(span)))
+    }
+
+    pub fn neg_elim(&mut self, id: usize, id_neg: usize) -> Result<usize, String> {
+        let form = self.line(id)?;
+        let neg = self.line(id_neg)?;
+        let map = self.parser.pattern_match(neg.subtree_root(), "!@a")?;
+        if map["a"] != form {
+            return Err("a mismatched pattern '[a], ![a]'".to_string());
+        }
+        Ok(self.push(Tree::new("F".to_string()), Rule::NegElim(id, id_neg)))
+    }
+
+    pub fn false_elim(&mut self, id: usize, form: Tree<String>) -> Result<usize, String> {
+        if self.line(id)?.get_root().unwrap() != "F" {
+            return Err(format!("{} is not F", id));
+        }
+        Ok(self.push(form, Rule::FalseElim(id)))
+    }
+
+    /// Derives `a` from a box from `!a` to `F`.
+    pub fn raa(&mut self, span: Span) -> Result<usize, String> {
+        let (assumption, form) = self.closed_box(span)?;
+        if form.get_root().unwrap() != "F" {
+            return Err("a mismatched pattern '![a] ... F'".to_string());
+        }
+        let map = self.parser.pattern_match(assumption.subtree_root(), "!@a")?;
+        Ok(self.push(map["a"].clone(), Rule::Raa(span)))
+    }
+
+    pub fn uq_intr(&mut self, id: usize, var: &str) -> Result<usize, String> {
+        let form = self.line(id)?;
+        if !self.parser.symdb().is_variable(var) {
+            return Err(format!("invalid variable name: {}", var));
+        }
+        self.check_eigenvariable(var)?;
+        let form = binary("∀", Tree::new(var.to_string()), form);
+        Ok(self.push(form, Rule::UqIntr(id, var.to_string())))
+    }
+
+    pub fn uq_elim(&mut self, id: usize, term: Tree<String>) -> Result<usize, String> {
+        let form = self.line(id)?;
+        let map = self.parser.pattern_match(form.subtree_root(), "@x ∀ @a")?;
+        let var = map["x"].get_root().unwrap();
+        let form = fol::substitute(&map["a"], var, term.subtree_root(), self.parser.symdb())?;
+        Ok(self.push(form, Rule::UqElim(id, term)))
+    }
+
+    /// Derives `form`, which is `x ∃ a`, from `a` with a term in place of `x` (`id`).
+    pub fn ex_intr(&mut self, id: usize, form: Tree<String>) -> Result<usize, String> {
+        let instance = self.line(id)?;
+        let map = self.parser.pattern_match(form.subtree_root(), "@x ∃ @a")?;
+        let var = map["x"].get_root().unwrap();
+        let mut term = None;
+        if !fol::find_instance(map["a"].subtree_root(), var, instance.subtree_root(), &mut term) {
+            return Err(format!("{} is not an instance", id));
+        }
+        let term = term.unwrap_or_else(|| map["x"].clone());
+        if fol::substitute(&map["a"], var, term.subtree_root(), self.parser.symdb())? != instance {
+            return Err(format!("{} is not an instance", id));
+        }
+        Ok(self.push(form, Rule::ExIntr(id)))
+    }
+
+    /// Derives `c` from `x ∃ a` (`id`) and a box from `a` to `c`,
+    /// where `x` is free neither in `c` nor in the open assumptions.
+    pub fn ex_elim(&mut self, id: usize, span: Span) -> Result<usize, String> {
+        let form = self.line(id)?;
+        let map = self.parser.pattern_match(form.subtree_root(), "@x ∃ @a")?;
+        let (assumption, conclusion) = self.closed_box(span)?;
+        if assumption != map["a"] {
+            return Err("a mismatched pattern 'x ∃ [a], [a] ... [c]'".to_string());
+        }
+        let var = map["x"].get_root().unwrap();
+        if fol::free_variables(conclusion.subtree_root(), self.parser.symdb()).contains(var) {
+            return Err(format!("{} occurs free in {}", var, self.parser.symdb().ast_to_string_minimal(&conclusion)));
+        }
+        self.check_eigenvariable(var)?;
+        Ok(self.push(conclusion, Rule::ExElim(id, span)))
+    }
+
+    /// Applies `rule`. `form` is the conclusion of the rules that need one.
+    pub fn apply(&mut self, rule: Rule, form: Option<Tree<String>>) -> Result<usize, String> {
+        let conclusion = || form.ok_or_else(|| "missing conclusion".to_string());
+        match rule {
+            Rule::Assume => Ok(self.assume(conclusion()?)),
+            Rule::Copy(id) => self.copy(id),
+            Rule::TrueIntr => Ok(self.true_intr()),
+            Rule::AndIntr(id1, id2) => self.and_intr(id1, id2),
+            Rule::AndElim1(id) => self.and_elim(id, 1),
+            Rule::AndElim2(id) => self.and_elim(id, 2),
+            Rule::OrIntr(id) => self.or_intr(id, conclusion()?),
+            Rule::OrElim(id, span1, span2) => self.or_elim(id, span1, span2),
+            Rule::ImpIntr(span) => self.imp_intr(span),
+            Rule::ImpElim(id_antec, id_imply) => self.imp_elim(id_antec, id_imply),
+            Rule::NegIntr(span) => self.neg_intr(span),
+            Rule::NegElim(id, id_neg) => self.neg_elim(id, id_neg),
+            Rule::FalseElim(id) => self.false_elim(id, conclusion()?),
+            Rule::Raa(span) => self.raa(span),
+            Rule::UqIntr(id, var) => self.uq_intr(id, &var),
+            Rule::UqElim(id, term) => self.uq_elim(id, term),
+            Rule::ExIntr(id) => self.ex_intr(id, conclusion()?),
+            Rule::ExElim(id, span) => self.ex_elim(id, span),
+        }
+    }
+
+    pub fn print_proof(&self, id: usize) {
+        let symdb = self.parser.symdb();
+        let Some(proof) = self.proofs.get(id) else {
+            return println!("an invalid proof ID: {}", id);
+        };
+        println!("{}: {}{}", id, "| ".repeat(proof.boxes.len()), symdb.ast_to_string_minimal(&proof.form));
+    }
+
+    pub fn rule_to_string(&self, rule: &Rule) -> String {
+        let symdb = self.parser.symdb();
+        match rule {
+            Rule::Assume => "assume".to_string(),
+            Rule::Copy(id) => format!("copy {}", id),
+            Rule::TrueIntr => "true-intr".to_string(),
+            Rule::AndIntr(id1, id2) => format!("and-intr {} {}", id1, id2),
+            Rule::AndElim1(id) => format!("and-elim1 {}", id),
+            Rule::AndElim2(id) => format!("and-elim2 {}", id),
+            Rule::OrIntr(id) => format!("or-intr {}", id),
+            Rule::OrElim(id, span1, span2) =>
+                format!("or-elim {} {} {}", id, span_to_string(*span1), span_to_string(*span2)),
+            Rule::ImpIntr(span) => format!("imp-intr {}", span_to_string(*span)),
+            Rule::ImpElim(id_antec, id_imply) => format!("imp-elim {} {}", id_antec, id_imply),
+            Rule::NegIntr(span) => format!("neg-intr {}", span_to_string(*span)),
+            Rule::NegElim(id, id_neg) => format!("neg-elim {} {}", id, id_neg),
+            Rule::FalseElim(id) => format!("false-elim {}", id),
+            Rule::Raa(span) => format!("raa {}", span_to_string(*span)),
+            Rule::UqIntr(id, var) => format!("uq-intr {} {}", id, var),
+            Rule::UqElim(id, term) => format!("uq-elim {} {}", id, symdb.ast_to_string_minimal(term)),
+            Rule::ExIntr(id) => format!("ex-intr {}", id),
+            Rule::ExElim(id, span) => format!("ex-elim {} {}", id, span_to_string(*span)),
+        }
+    }
+
+    /// Formats a proof as `ID: formula [rule]`, with a `| ` for each box containing it.
+    pub fn proof_to_string(&self, id: usize) -> Option<String> {
+        let symdb = self.parser.symdb();
+        let proof = self.proofs.get(id)?;
+        Some(format!("{}: {}{} [{}]", id, "| ".repeat(proof.boxes.len()),
+            symdb.ast_to_string_minimal(&proof.form), self.rule_to_string(&proof.rule)))
+    }
+
+    pub fn print_proofs(&self) {
+        for id in 0..self.proofs.len() {
+            println!("{}", self.proof_to_string(id).unwrap());
+        }
+    }
+
+    /// IDs of all proofs `id` depends on, including `id` itself, in ascending order.
+    pub fn derivation(&self, id: usize) -> Vec<usize> {
+        let mut used = vec![false; id + 1];
+        used[id] = true;
+        for i in (0..=id).rev() {
+            if used[i] {
+                for premise in self.proofs[i].rule.premises() {
+                    used[premise] = true;
+                }
+            }
+        }
+        (0..=id).filter(|&i| used[i]).collect()
+    }
+
+    pub fn print_derivation(&self, id: usize) {
+        if id >= self.proofs.len() {
+            return println!("an invalid proof ID: {}", id);
+        }
+        for i in self.derivation(id) {
+            println!("{}", self.proof_to_string(i).unwrap());
+        }
+    }
+
+    /// Parses the name and the arguments of a rule, leaving the conclusion of
+    /// `or-intr`, `false-elim` and `ex-intr` in `args`.
+    pub fn parse_rule(&mut self, args: &mut SplitWhitespace) -> Result<Rule, String> {
+        let Some(name) = args.next() else { return Err("missing rule".to_string()); };
+        let next_id = |args: &mut SplitWhitespace| -> Result<usize, String> {
+            let id = args.next().ok_or_else(|| format!("missing proof ID: {}", name))?;
+            id.parse().map_err(|_| format!("invalid proof ID: {}", id))
+        };
+        let next_span = |args: &mut SplitWhitespace| -> Result<Span, String> {
+            let span = args.next().ok_or_else(|| format!("missing box: {}", name))?;
+            span.split_once('-')
+                .and_then(|(first, last)| Some((first.parse().ok()?, last.parse().ok()?)))
+                .ok_or_else(|| format!("invalid box: {}", span))
+        };
+        let rule = match name {
+            "assume" => Rule::Assume,
+            "copy" => Rule::Copy(next_id(args)?),
+            "true-intr" => Rule::TrueIntr,
+            "and-intr" => Rule::AndIntr(next_id(args)?, next_id(args)?),
+            "and-elim1" => Rule::AndElim1(next_id(args)?),
+            "and-elim2" => Rule::AndElim2(next_id(args)?),
+            "or-intr" => Rule::OrIntr(next_id(args)?),
+            "or-elim" => Rule::OrElim(next_id(args)?, next_span(args)?, next_span(args)?),
+            "imp-intr" => Rule::ImpIntr(next_span(args)?),
+            "imp-elim" => Rule::ImpElim(next_id(args)?, next_id(args)?),
+            "neg-intr" => Rule::NegIntr(next_span(args)?),
+            "neg-elim" => Rule::NegElim(next_id(args)?, next_id(args)?),
+            "false-elim" => Rule::FalseElim(next_id(args)?),
+            "raa" => Rule::Raa(next_span(args)?),
+            "uq-intr" => {
+                let id = next_id(args)?;
+                let var = args.next().ok_or_else(|| format!("missing variable: {}", name))?;
+                Rule::UqIntr(id, var.to_string())
+            }
+            "uq-elim" => {
+                let id = next_id(args)?;
+                let term = self.parser.parse(args.remainder())?;
+                Rule::UqElim(id, term)
+            }
+            "ex-intr" => Rule::ExIntr(next_id(args)?),
+            "ex-elim" => Rule::ExElim(next_id(args)?, next_span(args)?),
+            other => return Err(format!("unknown rule: {}", other)),
+        };
+        Ok(rule)
+    }
+
+    pub fn save(&self, file_name: &str) -> std::io::Result<()> {
+        let mut buffer = BufWriter::new(File::create(file_name)?);
+        for id in 0..self.proofs.len() {
+            let proof_str = self.proof_to_string(id).unwrap() + "\n";
+            buffer.write_all(proof_str.as_bytes())?;
+        }
+        // Boxes closed after the last line cannot be told from its depth.
+        let depth = self.proofs.last().map_or(0, |proof| proof.boxes.len());
+        for _ in self.open.len()..depth {
+            buffer.write_all(b"end\n")?;
+        }
+        buffer.flush()?;
+        Ok(())
+    }
+
+    fn load_line(&mut self, line: &str) -> Result<(), String> {
+        if line.trim() == "end" {
+            return self.end().map(|_| ());
+        }
+        let (id, body) = line.split_once(':')
+            .ok_or_else(|| "missing proof ID".to_string())?;
+        let id: usize = id.trim().parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+        if id != self.proofs.len() {
+            return Err(format!("expected proof ID {}, found {}", self.proofs.len(), id));
+        }
+        let (form, rule) = body.split_once('[')
+            .and_then(|(form, rule)| Some((form, rule.trim_end().strip_suffix(']')?)))
+            .ok_or_else(|| "missing rule".to_string())?;
+        let mut form = form.trim_start();
+        let mut depth = 0;
+        while let Some(rem) = form.strip_prefix('|') {
+            form = rem.trim_start();
+            depth += 1;
+        }
+        let form = self.parser.parse(form)?;
+        let rule = self.parse_rule(&mut SplitWhitespace::from(rule))?;
+        // A line outside a box closes it, and so does an assumption at the same depth.
+        if rule == Rule::Assume && depth == 0 {
+            return Err("invalid depth of boxes".to_string());
+        }
+        let outer = if rule == Rule::Assume { depth - 1 } else { depth };
+        if outer > self.open.len() {
+            return Err("invalid depth of boxes".to_string());
+        }
+        self.open.truncate(outer);
+        let new_id = self.apply(rule, Some(form.clone()))?;
+        if self.proofs[new_id].form != form {
+            let symdb = self.parser.symdb();
+            return Err(format!("the rule derives {}", symdb.ast_to_string_minimal(&self.proofs[new_id].form)));
+        }
+        Ok(())
+    }
+
+    /// Loads a file written by `save`, checking every step.
+    /// On failure the current proofs are kept.
+    pub fn load(&mut self, file_name: &str) -> std::io::Result<()> {
+        let buffer = BufReader::new(File::open(file_name)?);
+        let old_proofs = std::mem::take(&mut self.proofs);
+        let old_open = std::mem::take(&mut self.open);
+        for (i, line) in buffer.lines().enumerate() {
+            let result = line.map_err(|err| err.to_string())
+                .and_then(|line| self.load_line(&line));
+            if let Err(err) = result {
+                self.proofs = old_proofs;
+                self.open = old_open;
+                return Err(Error::new(ErrorKind::Other, format!("line {}: {}", i + 1, err)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn command(&mut self, s: &str) -> Result<(), String> {
+        let mut args = SplitWhitespace::from(s);
+        let Some(cmd) = args.next() else { return Ok(()); };
+        match cmd {
+            "show" => {
+                if let Some(id) = args.next() {
+                    let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                    self.print_derivation(id);
+                } else {
+                    self.print_proofs();
+                }
+            }
+            "save" => {
+                let file_name = args.next().unwrap_or("default.txt");
+                if let Err(err) = self.save(file_name) {
+                    return Err(err.to_string());
+                }
+            }
+            "load" => {
+                let file_name = args.next().unwrap_or("default.txt");
+                if let Err(err) = self.load(file_name) {
+                    return Err(err.to_string());
+                }
+            }
+            "end" => {
+                let span = self.end()?;
+                println!("closed box {}", span_to_string(span));
+            }
+            _ => {
+                let mut args = SplitWhitespace::from(s);
+                let rule = self.parse_rule(&mut args)?;
+                let form = if rule.needs_conclusion() {
+                    Some(self.parser.parse(args.remainder())?)
+                } else {
+                    None
+                };
+                let new_id = self.apply(rule, form)?;
+                self.print_proof(new_id);
+            }
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxes() {
+        let mut sys = System::new("ops.txt").unwrap();
+        for command in ["assume a | b", "assume a", "or-intr 1 b | a", "end", "assume b", "or-intr 3 b | a",
+            "end", "or-elim 0 1-2 3-4", "end", "imp-intr 0-5"] {
+            sys.command(command).unwrap();
+        }
+        assert_eq!(sys.proof_to_string(6).unwrap(), "6: a | b -> b | a [imp-intr 0-5]");
+        assert!(sys.command("copy 1").is_err());
+        assert!(sys.command("imp-intr 1-2").is_err());
+
+        for command in ["assume x ∃ p = x", "assume p = x", "ex-intr 8 y ∃ y = x", "end"] {
+            sys.command(command).unwrap();
+        }
+        assert_eq!(sys.ex_elim(7, (8, 9)).unwrap_err(), "x occurs free in y ∃ y = x");
+        for command in ["end", "assume x ∃ p = x", "assume p = x", "ex-intr 11 y ∃ p = y", "end", "ex-elim 10 11-12"] {
+            sys.command(command).unwrap();
+        }
+        assert!(sys.command("uq-intr 13 p").is_err());
+        sys.command("end").unwrap();
+        sys.command("imp-intr 10-13").unwrap();
+        sys.command("uq-intr 14 p").unwrap();
+        sys.command("uq-elim 15 q").unwrap();
+        assert_eq!(sys.proof_to_string(16).unwrap(), "16: (x ∃ q = x) -> (y ∃ q = y) [uq-elim 15 q]");
+
+        let file_name = std::env::temp_dir().join("logic_nd_boxes.txt");
+        let file_name = file_name.to_str().unwrap();
+        sys.save(file_name).unwrap();
+        sys.load(file_name).unwrap();
+        assert_eq!(sys.proofs.len(), 17);
+
+        let mut sys = System::new("ops.txt").unwrap();
+        sys.command("assume a").unwrap();
+        sys.command("end").unwrap();
+        sys.save(file_name).unwrap();
+        sys.load(file_name).unwrap();
+        assert!(sys.open.is_empty());
+        std::fs::write(file_name, "0: a [assume]\n").unwrap();
+        assert!(sys.load(file_name).is_err());
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
@@ -1,13 +1,61 @@
-use tree::v3::{Tree, Subtree};
-use parser::parser::Parser;
-use crate::util::SplitWhitespace;
+use tree::v3::Tree;
+use parser::parser::{Parser, substitute_tree, unify_tree};
+use parser::sym::SymDB;
+use crate::util::{History, SplitWhitespace, parse_subst, subst_to_string};
+use crate::{dimacs, kalmar, latex, lk, metamath, sat, semantics, util};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write, BufWriter};
 use std::io::{Error, ErrorKind};
 
+/// The inference that produced a formula in `System::proofs`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Axiom,
+    Assume,
+    Mp(usize, usize),
+    Inst(usize, Vec<(String, Tree<String>)>),
+    D(usize, usize),
+}
+
+impl Rule {
+    /// IDs of the proofs this step was derived from.
+    pub fn premises(&self) -> Vec<usize> {
+        match *self {
+            Rule::Axiom | Rule::Assume => Vec::new(),
+            Rule::Mp(id_antec, id_imply) | Rule::D(id_antec, id_imply) => vec![id_antec, id_imply],
+            Rule::Inst(id, _) => vec![id],
+        }
+    }
+
+    /// The same rule with the premises renumbered by `f`.
+    pub fn remap(&self, f: impl Fn(usize) -> usize) -> Rule {
+        match self.clone() {
+            Rule::Axiom => Rule::Axiom,
+            Rule::Assume => Rule::Assume,
+            Rule::Mp(id_antec, id_imply) => Rule::Mp(f(id_antec), f(id_imply)),
+            Rule::D(id_antec, id_imply) => Rule::D(f(id_antec), f(id_imply)),
+            Rule::Inst(id, subst) => Rule::Inst(f(id), subst),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Proof {
+    pub form: Tree<String>,
+    pub rule: Rule,
+}
+
 pub struct System {
-    proofs: Vec<Tree<String>>,
+    axioms: Vec<Tree<String>>,
+    proofs: Vec<Proof>,
+    /// IDs of the open assumptions, innermost last.
+    contexts: Vec<usize>,
     parser: Parser,
+    /// `proofs` and `contexts` before each command that changed them.
+    history: History<(Vec<Proof>, Vec<usize>)>,
+    /// The file of the operator templates for `latex`.
+    latex_templates: String,
 }
 
 impl System {
@@ -15,26 +63,57 @@ impl System {
     pub fn new(op_file: &str, axiom_file: &str) -> Result<Self, String> {
         let mut parser = Parser::new(op_file)?;
         let reader_ax = BufReader::new(File::open(axiom_file).map_err(|err| err.to_string())?);
-        let mut proofs = Vec::new();
+        let mut axioms = Vec::new();
         for line in reader_ax.lines() {
             let line = line.map_err(|err| err.to_string())?;
             if line.is_empty() {
                 continue;
             }
-            proofs.push(parser.parse(&line)?);
+            axioms.push(parser.parse(&line)?);
         }
-        Ok(Self { proofs, parser })
+        let proofs = axioms.iter()
+            .map(|axiom| Proof { form: axiom.clone(), rule: Rule::Axiom })
+            .collect();
+        Ok(Self {
+            axioms, proofs, contexts: Vec::new(), parser, history: History::default(),
+            latex_templates: "latex.txt".to_string(),
+        })
+    }
+
+    /// Sets the file of the operator templates for `latex`, `latex.txt` by default.
+    pub fn set_latex_templates(&mut self, file_name: &str) {
+        self.latex_templates = file_name.to_string();
     }
 
-    pub fn inst(&mut self, id: usize, var: &str, replace: Subtree<'_, String>) -> Result<usize, String> {
+    pub fn get(&self, id: usize) -> Option<&Proof> {
+        self.proofs.get(id)
+    }
+
+    pub fn symdb(&self) -> &SymDB {
+        self.parser.symdb()
+    }
+
+    fn push(&mut self, form: Tree<String>, rule: Rule) -> usize {
+        let id = self.proofs.len();
+        self.proofs.push(Proof { form, rule });
+        id
+    }
+
+    /// Substitutes the variables in `subst` simultaneously.
+    pub fn inst(&mut self, id: usize, subst: &[(String, Tree<String>)]) -> Result<usize, String> {
         let Some(proof) = self.proofs.get(id) else {
             return Err(format!("there is no proof with ID {}", id));
         };
-        let mut new_proof = proof.clone();
-        new_proof.replace(Tree::new(var.to_string()).subtree_root(), replace);
-        let id = self.proofs.len();
-        self.proofs.push(new_proof);
-        Ok(id)
+        if let Some((var, _)) = subst.iter().find(|(var, _)| !self.parser.symdb().is_variable(var)) {
+            return Err(format!("{} is not a variable", var));
+        }
+        if let Some((var, &hyp)) = subst.iter()
+            .flat_map(|(var, _)| self.contexts.iter().map(move |hyp| (var, hyp)))
+            .find(|&(var, &hyp)| occurs(&self.proofs[hyp].form, var) && self.depends_on(id, hyp)) {
+            return Err(format!("{} occurs in the open assumption {}", var, hyp));
+        }
+        let new_proof = substitute_tree(proof.form.subtree_root(), &subst.iter().cloned().collect());
+        Ok(self.push(new_proof, Rule::Inst(id, subst.to_vec())))
     }
 
     pub fn mp(&mut self, id_antec: usize, id_imply: usize) -> Result<usize, String> {
@@ -44,18 +123,340 @@ impl System {
         let Some(proof_imply) = self.proofs.get(id_imply) else {
             return Err(format!("there is no proof with ID {}", id_imply));
         };
+        let proof_imply = &proof_imply.form;
         if proof_imply.get_root().unwrap() != "->" {
             return Err("a mismatched form '[a] -> [b]'".to_string());
         }
         let mut operands = proof_imply.children_root();
         let antecedent = proof_imply.subtree(operands.next().unwrap());
-        if proof_antec.subtree_root() != antecedent {
+        if proof_antec.form.subtree_root() != antecedent {
             return Err("a mismatched pattern '[a], [a] -> [b]'".to_string());
         }
         let consequent = proof_imply.clone_sub(operands.next().unwrap());
-        let id = self.proofs.len();
-        self.proofs.push(consequent);
-        Ok(id)
+        Ok(self.push(consequent, Rule::Mp(id_antec, id_imply)))
+    }
+
+    /// Condensed detachment: derives the most general `B` such that instances of `A` (`id_antec`)
+    /// and `A -> B` (`id_imply`) are an application of `mp`.
+    /// Variables of the open assumptions a premise depends on are not instantiated.
+    pub fn d(&mut self, id_antec: usize, id_imply: usize) -> Result<usize, String> {
+        for id in [id_antec, id_imply] {
+            if id >= self.proofs.len() {
+                return Err(format!("there is no proof with ID {}", id));
+            }
+        }
+        let (_, _, consequent) = self.condense(
+            (&self.proofs[id_antec].form, &self.frozen(id_antec)),
+            (&self.proofs[id_imply].form, &self.frozen(id_imply)),
+        )?;
+        Ok(self.push(consequent, Rule::D(id_antec, id_imply)))
+    }
+
+    /// The substitutions for the variables of `A` and `A' -> B` that make the `D` step `id`
+    /// an application of `mp`.
+    #[allow(clippy::type_complexity)]
+    pub fn d_substitutions(&self, id: usize) -> Result<(Vec<(String, Tree<String>)>, Vec<(String, Tree<String>)>), String> {
+        let Some(Proof { form, rule: Rule::D(id_antec, id_imply) }) = self.proofs.get(id) else {
+            return Err(format!("{} is not derived by D", id));
+        };
+        let (subst_antec, subst_imply, consequent) = self.condense(
+            (&self.proofs[*id_antec].form, &self.frozen(*id_antec)),
+            (&self.proofs[*id_imply].form, &self.frozen(*id_imply)),
+        )?;
+        if &consequent != form {
+            return Err(format!("D {} {} no longer derives {}", id_antec, id_imply, self.parser.symdb().ast_to_string_minimal(form)));
+        }
+        Ok((subst_antec, subst_imply))
+    }
+
+    /// Variables occurring in the open assumptions the proof `id` depends on.
+    fn frozen(&self, id: usize) -> HashSet<String> {
+        let symdb = self.parser.symdb();
+        self.contexts.iter()
+            .filter(|&&hyp| self.depends_on(id, hyp))
+            .flat_map(|&hyp| {
+                let form = &self.proofs[hyp].form;
+                form.ids().map(|id| form.get(id).unwrap().clone()).collect::<Vec<_>>()
+            })
+            .filter(|value| symdb.is_variable(value))
+            .collect()
+    }
+
+    /// Unifies `A`, with its variables renamed apart, and the antecedent of `A' -> B`,
+    /// each given with the variables that must be kept unchanged.
+    /// Returns the substitutions for the variables of both formulas and the instantiated `B`.
+    #[allow(clippy::type_complexity)]
+    fn condense(&self, (form_antec, frozen_antec): (&Tree<String>, &HashSet<String>),
+    (form_imply, frozen_imply): (&Tree<String>, &HashSet<String>))
+    -> Result<(Vec<(String, Tree<String>)>, Vec<(String, Tree<String>)>, Tree<String>), String> {
+        if form_imply.get_root().unwrap() != "->" {
+            return Err("a mismatched form '[a] -> [b]'".to_string());
+        }
+        let symdb = self.parser.symdb();
+        let vars = |form: &Tree<String>| -> Vec<String> {
+            let mut vars: Vec<String> = Vec::new();
+            for id in form.ids() {
+                let value = form.get(id).unwrap();
+                if symdb.is_variable(value) && !vars.contains(value) {
+                    vars.push(value.clone());
+                }
+            }
+            vars
+        };
+        let vars_antec = vars(form_antec);
+        let vars_imply = vars(form_imply);
+        let mut used: HashSet<String> = vars_antec.iter().chain(vars_imply.iter()).cloned().collect();
+        // A variable shared by both formulas is renamed on a side where it may be instantiated,
+        // and is kept as a constant if it is frozen on both sides.
+        let (mut rename_antec, mut rename_imply) = (HashMap::new(), HashMap::new());
+        for var in vars_antec.iter().filter(|&var| vars_imply.contains(var)) {
+            let rename = if !frozen_antec.contains(var) {
+                &mut rename_antec
+            } else if !frozen_imply.contains(var) {
+                &mut rename_imply
+            } else {
+                continue;
+            };
+            let fresh = fresh_variable(&used);
+            used.insert(fresh.clone());
+            rename.insert(var.clone(), Tree::new(fresh));
+        }
+        let renamed_antec = substitute_tree(form_antec.subtree_root(), &rename_antec);
+        let renamed_imply = substitute_tree(form_imply.subtree_root(), &rename_imply);
+        let mut operands = renamed_imply.children_root();
+        let antecedent = renamed_imply.subtree(operands.next().unwrap());
+        let is_variable = |value: &str| symdb.is_variable(value)
+            && !frozen_antec.contains(value) && !frozen_imply.contains(value);
+        let Some(mgu) = unify_tree(renamed_antec.subtree_root(), antecedent, is_variable) else {
+            return Err("the antecedent does not unify".to_string());
+        };
+        let consequent = substitute_tree(renamed_imply.subtree(operands.next().unwrap()), &mgu);
+        let restrict = |vars: &[String], rename: &HashMap<String, Tree<String>>| -> Vec<(String, Tree<String>)> {
+            vars.iter()
+                .map(|var| {
+                    let renamed = rename.get(var).cloned().unwrap_or_else(|| Tree::new(var.clone()));
+                    (var.clone(), substitute_tree(renamed.subtree_root(), &mgu))
+                })
+                .filter(|(var, replace)| replace.get_root() != Some(var))
+                .collect()
+        };
+        Ok((restrict(&vars_antec, &rename_antec), restrict(&vars_imply, &rename_imply), consequent))
+    }
+
+    /// Removes the proof `id` and every later proof that depends on it, and renumbers the rest.
+    /// Returns the IDs of the removed proofs.
+    pub fn retract(&mut self, id: usize) -> Result<Vec<usize>, String> {
+        let Some(proof) = self.proofs.get(id) else {
+            return Err(format!("there is no proof with ID {}", id));
+        };
+        if proof.rule == Rule::Axiom {
+            return Err(format!("{} is an axiom", id));
+        }
+        let new_ids = util::retract(&mut self.proofs, id, |proof| proof.rule.premises(),
+            |proof, f| Proof { form: proof.form, rule: proof.rule.remap(f) });
+        self.contexts = self.contexts.iter().filter_map(|&hyp| new_ids[hyp]).collect();
+        Ok((0..new_ids.len()).filter(|&i| new_ids[i].is_none()).collect())
+    }
+
+    /// Returns whether the proof `id` was derived using the assumption `hyp`.
+    pub fn depends_on(&self, id: usize, hyp: usize) -> bool {
+        if id < hyp {
+            return false;
+        }
+        let mut used = vec![false; id - hyp + 1];
+        used[id - hyp] = true;
+        for i in (hyp..=id).rev() {
+            if used[i - hyp] {
+                for premise in self.proofs[i].rule.premises() {
+                    if premise >= hyp {
+                        used[premise - hyp] = true;
+                    }
+                }
+            }
+        }
+        used[0]
+    }
+
+    /// Opens a hypothetical context in which `form` is assumed.
+    pub fn assume(&mut self, form: Tree<String>) -> usize {
+        let id = self.push(form, Rule::Assume);
+        self.contexts.push(id);
+        id
+    }
+
+    /// Closes the innermost context with the assumption `A` and the last proof `B`,
+    /// and derives `A -> B` by the deduction theorem.
+    /// The proofs in the context are replaced by `mp`/`inst` steps over the axioms.
+    pub fn discharge(&mut self) -> Result<usize, String> {
+        let Some(last) = self.proofs.len().checked_sub(1) else {
+            return Err("no open assumption".to_string());
+        };
+        self.discharge_at(last)
+    }
+
+    /// Same as `discharge`, but derives `A -> B` for the proof `B` (`id`) instead of the last one.
+    pub fn discharge_at(&mut self, id: usize) -> Result<usize, String> {
+        let Some(&hyp) = self.contexts.last() else {
+            return Err("no open assumption".to_string());
+        };
+        if id >= self.proofs.len() {
+            return Err(format!("there is no proof with ID {}", id));
+        }
+        let ctx = self.proofs.split_off(hyp);
+        self.contexts.pop();
+        let result = self.discharge_sub(hyp, &ctx, id);
+        if result.is_err() {
+            self.proofs.truncate(hyp);
+            self.proofs.extend(ctx);
+            self.contexts.push(hyp);
+        }
+        result
+    }
+
+    fn discharge_sub(&mut self, hyp: usize, ctx: &[Proof], target: usize) -> Result<usize, String> {
+        let k = self.axiom_id("@a -> @b -> @a", &["a", "b"])?;
+        let s = self.axiom_id("(@a -> @b -> @c) -> (@a -> @b) -> @a -> @c", &["a", "b", "c"])?;
+        let assumption = ctx[0].form.clone();
+        // For each proof `B` in the context, the new ID of `A -> B` if `B` depends on
+        // the assumption `A`, or the new ID of `B` itself otherwise.
+        let mut new_ids: Vec<usize> = Vec::with_capacity(ctx.len());
+        let mut dependent = Vec::with_capacity(ctx.len());
+        // IDs of `A -> B` derived for proofs `B` that do not depend on the assumption.
+        let mut weakened = HashMap::new();
+        for (i, proof) in ctx.iter().enumerate() {
+            let is_dependent = i == 0 || proof.rule.premises().iter()
+                .any(|&premise| premise >= hyp && dependent[premise - hyp]);
+            let remap = |premise: usize| if premise < hyp { premise } else { new_ids[premise - hyp] };
+            let new_id = match proof.rule {
+                Rule::Axiom => self.push(proof.form.clone(), Rule::Axiom),
+                Rule::Assume if i == 0 => self.identity(&k, &s, &assumption)?,
+                Rule::Assume => unreachable!("nested contexts are discharged first"),
+                Rule::Inst(id, ref subst) => self.inst(remap(id), subst)?,
+                Rule::Mp(id_antec, id_imply) if !is_dependent => self.mp(remap(id_antec), remap(id_imply))?,
+                Rule::D(id_antec, id_imply) if !is_dependent => self.d(remap(id_antec), remap(id_imply))?,
+                Rule::Mp(id_antec, id_imply) => {
+                    let mut imps = [0; 2];
+                    for (imp, premise) in imps.iter_mut().zip([id_antec, id_imply]) {
+                        *imp = if premise >= hyp && dependent[premise - hyp] {
+                            new_ids[premise - hyp]
+                        } else if let Some(&id) = weakened.get(&premise) {
+                            id
+                        } else {
+                            let id = self.weaken(&k, remap(premise), &assumption)?;
+                            weakened.insert(premise, id);
+                            id
+                        };
+                    }
+                    let antecedent = if id_antec < hyp {
+                        self.proofs[id_antec].form.clone()
+                    } else {
+                        ctx[id_antec - hyp].form.clone()
+                    };
+                    self.mp_under(&s, &assumption, antecedent, proof.form.clone(), imps)?
+                }
+                Rule::D(id_antec, id_imply) => {
+                    // `D` is `inst` on both premises followed by `mp`, with the same substitutions
+                    // as when the context was open.
+                    let form = |premise: usize| if premise < hyp {
+                        self.proofs[premise].form.clone()
+                    } else {
+                        ctx[premise - hyp].form.clone()
+                    };
+                    let frozen = |premise: usize| {
+                        let mut frozen = self.frozen(remap(premise));
+                        if premise >= hyp && dependent[premise - hyp] {
+                            frozen.extend(assumption.ids()
+                                .map(|id| assumption.get(id).unwrap().clone())
+                                .filter(|value| self.symdb().is_variable(value)));
+                        }
+                        frozen
+                    };
+                    let (form_antec, form_imply) = (form(id_antec), form(id_imply));
+                    let (subst_antec, subst_imply, _) = self.condense(
+                        (&form_antec, &frozen(id_antec)),
+                        (&form_imply, &frozen(id_imply)),
+                    )?;
+                    let antecedent = substitute_tree(form_antec.subtree_root(), &subst_antec.iter().cloned().collect());
+                    let mut imps = [0; 2];
+                    for (imp, (premise, subst)) in imps.iter_mut().zip([(id_antec, &subst_antec), (id_imply, &subst_imply)]) {
+                        *imp = if premise >= hyp && dependent[premise - hyp] {
+                            self.inst(new_ids[premise - hyp], subst)?
+                        } else {
+                            let id = self.inst(remap(premise), subst)?;
+                            self.weaken(&k, id, &assumption)?
+                        };
+                    }
+                    self.mp_under(&s, &assumption, antecedent, proof.form.clone(), imps)?
+                }
+            };
+            new_ids.push(new_id);
+            dependent.push(is_dependent);
+        }
+        if target < hyp {
+            self.weaken(&k, target, &assumption)
+        } else if dependent[target - hyp] {
+            Ok(new_ids[target - hyp])
+        } else {
+            self.weaken(&k, new_ids[target - hyp], &assumption)
+        }
+    }
+
+    /// Derives `A -> C` from `A -> B` (`imps[0]`) and `A -> B -> C` (`imps[1]`)
+    /// by the axiom `(a -> b -> c) -> (a -> b) -> a -> c` (`s`).
+    fn mp_under(&mut self, s: &(usize, Vec<String>), assumption: &Tree<String>,
+    antecedent: Tree<String>, consequent: Tree<String>, imps: [usize; 2]) -> Result<usize, String> {
+        let s_inst = self.inst(s.0, &[
+            (s.1[0].clone(), assumption.clone()),
+            (s.1[1].clone(), antecedent),
+            (s.1[2].clone(), consequent),
+        ])?;
+        let step = self.mp(imps[1], s_inst)?;
+        self.mp(imps[0], step)
+    }
+
+    /// Finds an axiom matching `pat`, whose pattern variables `vars` must be distinct variables.
+    /// Returns the ID of the axiom, added to the proofs if necessary, and the variables of the axiom.
+    pub(crate) fn axiom_id(&mut self, pat: &str, vars: &[&str]) -> Result<(usize, Vec<String>), String> {
+        for axiom in self.axioms.clone() {
+            let Ok(map) = self.parser.pattern_match(axiom.subtree_root(), pat) else { continue; };
+            if vars.iter().any(|&var| map[var].children_root().next().is_some()) {
+                continue;
+            }
+            let axiom_vars: Vec<String> = vars.iter()
+                .map(|&var| map[var].get_root().unwrap().clone())
+                .collect();
+            let distinct = axiom_vars.iter().collect::<HashSet<_>>().len() == vars.len();
+            if !distinct || axiom_vars.iter().any(|var| !self.parser.symdb().is_variable(var)) {
+                continue;
+            }
+            let id = self.proofs.iter()
+                .position(|proof| proof.rule == Rule::Axiom && proof.form == axiom);
+            let id = id.unwrap_or_else(|| self.push(axiom, Rule::Axiom));
+            return Ok((id, axiom_vars));
+        }
+        Err(format!("no axiom of the form {}", pat.replace('@', "")))
+    }
+
+    /// Derives `A -> B` from `B` (`id`) by the axiom `a -> b -> a` (`k`).
+    fn weaken(&mut self, k: &(usize, Vec<String>), id: usize, assumption: &Tree<String>) -> Result<usize, String> {
+        let form = self.proofs[id].form.clone();
+        let k_inst = self.inst(k.0, &[(k.1[0].clone(), form), (k.1[1].clone(), assumption.clone())])?;
+        self.mp(id, k_inst)
+    }
+
+    /// Derives `A -> A` by the axioms `a -> b -> a` (`k`) and
+    /// `(a -> b -> c) -> (a -> b) -> a -> c` (`s`).
+    fn identity(&mut self, k: &(usize, Vec<String>), s: &(usize, Vec<String>), form: &Tree<String>) -> Result<usize, String> {
+        let form_imply = imply(form.clone(), form.clone());
+        let s_inst = self.inst(s.0, &[
+            (s.1[0].clone(), form.clone()),
+            (s.1[1].clone(), form_imply.clone()),
+            (s.1[2].clone(), form.clone()),
+        ])?;
+        let k_inst = self.inst(k.0, &[(k.1[0].clone(), form.clone()), (k.1[1].clone(), form_imply)])?;
+        let step = self.mp(k_inst, s_inst)?;
+        let k_inst = self.inst(k.0, &[(k.1[0].clone(), form.clone()), (k.1[1].clone(), form.clone())])?;
+        self.mp(k_inst, step)
     }
 
     pub fn print_proof(&self, id: usize) {
@@ -63,44 +464,188 @@ impl System {
         let Some(proof) = self.proofs.get(id) else {
             return println!("an invalid proof ID: {}", id);
         };
-        println!("{}: {}", id, symdb.ast_to_string_minimal(proof));
+        println!("{}: {}", id, symdb.ast_to_string_minimal(&proof.form));
     }
 
-    pub fn print_proofs(&self) {
+    pub fn rule_to_string(&self, rule: &Rule) -> String {
+        let symdb = self.parser.symdb();
+        match rule {
+            Rule::Axiom => "axiom".to_string(),
+            Rule::Assume => "assume".to_string(),
+            Rule::Mp(id_antec, id_imply) => format!("mp {} {}", id_antec, id_imply),
+            Rule::D(id_antec, id_imply) => format!("D {} {}", id_antec, id_imply),
+            Rule::Inst(id, subst) => format!("inst {} {}", id, subst_to_string(subst, symdb)),
+        }
+    }
+
+    /// Formats a proof as `ID: formula [rule]`.
+    pub fn proof_to_string(&self, id: usize) -> Option<String> {
         let symdb = self.parser.symdb();
-        for (id, proof) in self.proofs.iter().enumerate() {
-            println!("{}: {}", id, symdb.ast_to_string_minimal(proof));
+        let proof = self.proofs.get(id)?;
+        Some(format!("{}: {} [{}]", id, symdb.ast_to_string_minimal(&proof.form), self.rule_to_string(&proof.rule)))
+    }
+
+    pub fn print_proofs(&self) {
+        for id in 0..self.proofs.len() {
+            println!("{}", self.proof_to_string(id).unwrap());
+        }
+    }
+
+    /// IDs of all proofs `id` depends on, including `id` itself, in ascending order.
+    pub fn derivation(&self, id: usize) -> Vec<usize> {
+        let mut used = vec![false; id + 1];
+        used[id] = true;
+        for i in (0..=id).rev() {
+            if used[i] {
+                for premise in self.proofs[i].rule.premises() {
+                    used[premise] = true;
+                }
+            }
+        }
+        (0..=id).filter(|&i| used[i]).collect()
+    }
+
+    pub fn print_derivation(&self, id: usize) {
+        if id >= self.proofs.len() {
+            return println!("an invalid proof ID: {}", id);
+        }
+        for i in self.derivation(id) {
+            println!("{}", self.proof_to_string(i).unwrap());
+        }
+    }
+
+    pub fn parse_rule(&mut self, s: &str) -> Result<Rule, String> {
+        let mut args = SplitWhitespace::from(s);
+        let Some(name) = args.next() else { return Err("missing rule".to_string()); };
+        let next_id = |args: &mut SplitWhitespace| -> Result<usize, String> {
+            let id = args.next().ok_or_else(|| format!("missing proof ID: {}", s))?;
+            id.parse().map_err(|_| format!("invalid proof ID: {}", id))
+        };
+        match name {
+            "axiom" => Ok(Rule::Axiom),
+            "assume" => Ok(Rule::Assume),
+            "mp" => {
+                let id_antec = next_id(&mut args)?;
+                let id_imply = next_id(&mut args)?;
+                Ok(Rule::Mp(id_antec, id_imply))
+            }
+            "D" => {
+                let id_antec = next_id(&mut args)?;
+                let id_imply = next_id(&mut args)?;
+                Ok(Rule::D(id_antec, id_imply))
+            }
+            "inst" => {
+                let id = next_id(&mut args)?;
+                Ok(Rule::Inst(id, parse_subst(&mut self.parser, args.remainder())?))
+            }
+            other => Err(format!("unknown rule: {}", other)),
         }
     }
 
     pub fn save(&self, file_name: &str) -> std::io::Result<()> {
         let mut buffer = BufWriter::new(File::create(file_name)?);
-        let symdb = self.parser.symdb();
-        for proof in self.proofs.iter() {
-            let proof_str = symdb.ast_to_string_minimal(proof) + "\n";
+        for id in 0..self.proofs.len() {
+            let proof_str = self.proof_to_string(id).unwrap() + "\n";
             buffer.write_all(proof_str.as_bytes())?;
         }
         buffer.flush()?;
         Ok(())
     }
 
+    /// Derives `form` again by `rule` and checks that the result is `form`.
+    fn replay(&mut self, form: Tree<String>, rule: Rule) -> Result<usize, String> {
+        let id = match rule {
+            Rule::Axiom => {
+                if !self.axioms.contains(&form) {
+                    return Err("not an axiom".to_string());
+                }
+                self.push(form.clone(), Rule::Axiom)
+            }
+            Rule::Assume => self.assume(form.clone()),
+            Rule::Mp(id_antec, id_imply) => self.mp(id_antec, id_imply)?,
+            Rule::D(id_antec, id_imply) => self.d(id_antec, id_imply)?,
+            Rule::Inst(id, subst) => self.inst(id, &subst)?,
+        };
+        if self.proofs[id].form != form {
+            let symdb = self.parser.symdb();
+            return Err(format!("the rule derives {}", symdb.ast_to_string_minimal(&self.proofs[id].form)));
+        }
+        Ok(id)
+    }
+
+    fn load_line(&mut self, line: &str) -> Result<(), String> {
+        let (id, body) = line.split_once(':')
+            .ok_or_else(|| "missing proof ID".to_string())?;
+        let id: usize = id.trim().parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+        if id != self.proofs.len() {
+            return Err(format!("expected proof ID {}, found {}", self.proofs.len(), id));
+        }
+        let (form, rule) = body.split_once('[')
+            .and_then(|(form, rule)| Some((form, rule.trim_end().strip_suffix(']')?)))
+            .ok_or_else(|| "missing rule".to_string())?;
+        let form = self.parser.parse(form)?;
+        let rule = self.parse_rule(rule)?;
+        self.replay(form, rule)?;
+        Ok(())
+    }
+
+    /// Loads a file written by `save`, checking every step against the axioms.
+    /// On failure the current proofs are kept.
     pub fn load(&mut self, file_name: &str) -> std::io::Result<()> {
         let buffer = BufReader::new(File::open(file_name)?);
-        self.proofs.clear();
-        for line in buffer.lines() {
-            let proof = self.parser.parse(&line?)
-                .map_err(|err| Error::new(ErrorKind::Other, err))?;
-            self.proofs.push(proof);
+        let old_proofs = std::mem::take(&mut self.proofs);
+        let old_contexts = std::mem::take(&mut self.contexts);
+        for (i, line) in buffer.lines().enumerate() {
+            let result = line.map_err(|err| err.to_string())
+                .and_then(|line| self.load_line(&line));
+            if let Err(err) = result {
+                self.proofs = old_proofs;
+                self.contexts = old_contexts;
+                return Err(Error::new(ErrorKind::Other, format!("line {}: {}", i + 1, err)));
+            }
         }
         Ok(())
     }
 
+    /// Runs `change`, a command step that changes the proofs, and records the state before it for `undo`.
+    fn record<T>(&mut self, change: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        let before = (self.proofs.clone(), self.contexts.clone());
+        let result = change(self)?;
+        self.history.record(before);
+        Ok(result)
+    }
+
+    /// Runs a command. `undo` and `redo` go back and forth over the commands that changed the proofs.
     pub fn command(&mut self, s: &str) -> Result<(), String> {
+        match s.trim() {
+            "undo" | "redo" => {
+                let state = (self.proofs.clone(), self.contexts.clone());
+                let state = if s.trim() == "undo" {
+                    self.history.undo(state).ok_or_else(|| "nothing to undo".to_string())?
+                } else {
+                    self.history.redo(state).ok_or_else(|| "nothing to redo".to_string())?
+                };
+                (self.proofs, self.contexts) = state;
+                if let Some(id) = self.proofs.len().checked_sub(1) {
+                    self.print_proof(id);
+                }
+                Ok(())
+            }
+            _ => self.run_command(s),
+        }
+    }
+
+    fn run_command(&mut self, s: &str) -> Result<(), String> {
         let mut args = SplitWhitespace::from(s);
         let Some(cmd) = args.next() else { return Ok(()); };
         match cmd {
             "show" => {
-                self.print_proofs();
+                if let Some(id) = args.next() {
+                    let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                    self.print_derivation(id);
+                } else {
+                    self.print_proofs();
+                }
             }
             "save" => {
                 let file_name = args.next().unwrap_or("default.txt");
@@ -110,25 +655,106 @@ impl System {
             }
             "load" => {
                 let file_name = args.next().unwrap_or("default.txt");
-                if let Err(err) = self.load(file_name) {
-                    return Err(err.to_string());
-                }
+                self.record(|sys| sys.load(file_name).map_err(|err| err.to_string()))?;
             }
             "mp" => {
                 let Some(ant_id) = args.next() else { return Err("E1".to_string()); };
                 let Some(imp_id) = args.next() else { return Err("E2".to_string()); };
                 let ant_id: usize = ant_id.parse().map_err(|_| "E3".to_string())?;
                 let imp_id: usize = imp_id.parse().map_err(|_| "E4".to_string())?;
-                let new_id = self.mp(ant_id, imp_id)?;
+                let new_id = self.record(|sys| sys.mp(ant_id, imp_id))?;
+                self.print_proof(new_id);
+            }
+            "D" => {
+                let Some(ant_id) = args.next() else { return Err("E1".to_string()); };
+                let Some(imp_id) = args.next() else { return Err("E2".to_string()); };
+                let ant_id: usize = ant_id.parse().map_err(|_| "E3".to_string())?;
+                let imp_id: usize = imp_id.parse().map_err(|_| "E4".to_string())?;
+                let new_id = self.record(|sys| sys.d(ant_id, imp_id))?;
                 self.print_proof(new_id);
             }
             "inst" => {
                 let Some(id) = args.next() else { return Err("".to_string()); };
                 let id: usize = id.parse().map_err(|_| "".to_string())?;
-                let Some(var) = args.next() else { return Err("".to_string()); };
-                let rem = args.remainder();
-                let replace = self.parser.parse(rem).map_err(|_| "".to_string())?;
-                let new_id = self.inst(id, var, replace.subtree_root())?;
+                let subst = parse_subst(&mut self.parser, args.remainder())?;
+                let new_id = self.record(|sys| sys.inst(id, &subst))?;
+                self.print_proof(new_id);
+            }
+            "retract" => {
+                let Some(id) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                let removed: Vec<String> = self.record(|sys| sys.retract(id))?.iter().map(|id| id.to_string()).collect();
+                println!("retracted {}", removed.join(", "));
+            }
+            "assume" => {
+                let form = self.parser.parse(args.remainder())?;
+                let new_id = self.record(|sys| Ok(sys.assume(form)))?;
+                self.print_proof(new_id);
+            }
+            "discharge" => {
+                let new_id = self.record(|sys| sys.discharge())?;
+                self.print_proof(new_id);
+            }
+            "taut" => {
+                let form = self.parser.parse(args.remainder())?;
+                let symdb = self.parser.symdb();
+                match semantics::tautology(form.subtree_root(), symdb)? {
+                    None => println!("valid"),
+                    Some(assign) => {
+                        let vars = semantics::variables(form.subtree_root(), symdb);
+                        println!("invalid: false when {}", semantics::assignment_to_string(&vars, &assign));
+                    }
+                }
+            }
+            "sat" => {
+                let form = self.parser.parse(args.remainder())?;
+                let symdb = self.parser.symdb();
+                match sat::solve(form.subtree_root(), symdb)? {
+                    None => println!("unsatisfiable"),
+                    Some(model) => {
+                        let vars = semantics::variables(form.subtree_root(), symdb);
+                        println!("satisfiable: {}", semantics::assignment_to_string(&vars, &model));
+                    }
+                }
+            }
+            "export-mm" => {
+                let Some(file_name) = args.next() else { return Err("missing file name".to_string()); };
+                let file = File::create(file_name).map_err(|err| err.to_string())?;
+                metamath::write(self, BufWriter::new(file))?;
+            }
+            "latex" => latex::command(self, &mut args, &self.latex_templates)?,
+            "export-cnf" => {
+                let Some(id) = args.next() else { return Err("missing proof ID".to_string()); };
+                let id: usize = id.parse().map_err(|_| format!("invalid proof ID: {}", id))?;
+                let Some(file_name) = args.next() else { return Err("missing file name".to_string()); };
+                let Some(proof) = self.proofs.get(id) else {
+                    return Err(format!("there is no proof with ID {}", id));
+                };
+                let (cnf, names) = dimacs::ast_to_cnf(proof.form.subtree_root(), self.parser.symdb())?;
+                let file = File::create(file_name).map_err(|err| err.to_string())?;
+                dimacs::write(&cnf, &names, BufWriter::new(file)).map_err(|err| err.to_string())?;
+                println!("{} variables, {} clauses", cnf.num_vars, cnf.clauses.len());
+            }
+            "import-cnf" => {
+                let Some(file_name) = args.next() else { return Err("missing file name".to_string()); };
+                let file = File::open(file_name).map_err(|err| err.to_string())?;
+                let (cnf, names) = dimacs::read(BufReader::new(file))?;
+                if let Some(name) = names.iter().find(|name| !self.parser.symdb().is_variable(name)) {
+                    return Err(format!("{} is not a variable", name));
+                }
+                let new_id = self.record(|sys| Ok(sys.assume(dimacs::cnf_to_ast(&cnf, &names))))?;
+                println!("assumed:");
+                self.print_proof(new_id);
+            }
+            "lk" => {
+                let form = self.parser.parse(args.remainder())?;
+                let symdb = self.parser.symdb();
+                let deriv = lk::prove(&form, symdb, lk::MAX_INSTANCES)?;
+                print!("{}", lk::deriv_to_string(&deriv, symdb));
+            }
+            "prove" => {
+                let form = self.parser.parse(args.remainder())?;
+                let new_id = self.record(|sys| kalmar::prove(sys, &form))?;
                 self.print_proof(new_id);
             }
             other => { return Err(format!("unknown command: {}", other)); }
@@ -137,3 +763,170 @@ impl System {
     }
 
 }
+
+impl latex::Proofs for System {
+    fn symdb(&self) -> &SymDB {
+        self.symdb()
+    }
+
+    fn num_proofs(&self) -> usize {
+        self.proofs.len()
+    }
+
+    fn derivation(&self, id: usize) -> Vec<usize> {
+        self.derivation(id)
+    }
+
+    fn line(&self, id: usize, templates: &HashMap<String, String>) -> latex::Line {
+        let symdb = self.parser.symdb();
+        let proof = &self.proofs[id];
+        let line = latex::Line::new(id, &proof.form, &self.rule_to_string(&proof.rule),
+            proof.rule.premises(), symdb, templates);
+        match &proof.rule {
+            Rule::Inst(_, subst) => line.with_subst(subst, symdb, templates),
+            _ => line,
+        }
+    }
+}
+
+/// Builds `antecedent -> consequent`.
+pub fn imply(antecedent: Tree<String>, consequent: Tree<String>) -> Tree<String> {
+    let mut tree = Tree::new("->".to_string());
+    tree.push_tree(tree.root_id(), antecedent);
+    tree.push_tree(tree.root_id(), consequent);
+    tree
+}
+
+fn occurs(form: &Tree<String>, var: &str) -> bool {
+    form.ids().any(|id| form.get(id).unwrap() == var)
+}
+
+/// Returns a variable name `v0`, `v1`, ... not in `used`.
+fn fresh_variable(used: &HashSet<String>) -> String {
+    (0..).map(|i| format!("v{}", i))
+        .find(|var| !used.contains(var))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derivation() {
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        for cmd in ["inst 0 b b -> a", "inst 1 c a", "inst 4 b b -> a", "mp 3 5", "mp 0 6"] {
+            sys.command(cmd).unwrap();
+        }
+        assert_eq!(sys.get(6).unwrap().rule, Rule::Mp(3, 5));
+        assert_eq!(sys.derivation(7), vec![0, 1, 3, 4, 5, 6, 7]);
+        assert_eq!(sys.proof_to_string(3).unwrap(), "3: a -> (b -> a) -> a [inst 0 b := b -> a]");
+        sys.command("inst 0 a := b, b := a").unwrap();
+        assert_eq!(sys.proof_to_string(8).unwrap(), "8: b -> a -> b [inst 0 a := b, b := a]");
+        assert!(sys.command("inst 0 a := b, a := c").is_err());
+    }
+
+    #[test]
+    fn discharge() {
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        for cmd in ["assume a -> b", "assume b -> c", "assume a", "mp 5 3", "mp 6 4"] {
+            sys.command(cmd).unwrap();
+        }
+        assert!(sys.command("inst 6 a c").is_err());
+        for _ in 0..3 {
+            sys.discharge().unwrap();
+        }
+        assert!(sys.discharge().is_err());
+        let last = sys.proofs.last().unwrap();
+        let expected = sys.parser.parse("(a -> b) -> (b -> c) -> a -> c").unwrap();
+        assert_eq!(last.form, expected);
+        assert!(sys.proofs.iter().all(|proof| proof.rule != Rule::Assume));
+    }
+
+    #[test]
+    fn condensed_detachment() {
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        sys.command("D 0 1").unwrap();
+        sys.command("D 0 3").unwrap();
+        assert_eq!(sys.proofs[4].form, sys.parser.parse("a -> a").unwrap());
+        assert!(sys.command("D 0 2").is_err());
+        for cmd in ["assume q", "D 5 0", "D 6 3"] {
+            sys.command(cmd).unwrap();
+        }
+        // `q` is the assumption and stays unchanged.
+        assert_eq!(sys.proofs[7].form, sys.parser.parse("a -> a").unwrap());
+        sys.command("D 5 6").unwrap();
+        sys.discharge().unwrap();
+        let last = sys.proofs.last().unwrap();
+        assert_eq!(last.form, sys.parser.parse("q -> q").unwrap());
+        assert!(sys.proofs.iter().all(|proof| proof.rule != Rule::Assume));
+    }
+
+    #[test]
+    fn retract() {
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        for cmd in ["inst 0 b := c", "assume q", "inst 0 a := q", "mp 4 5", "D 0 1"] {
+            sys.command(cmd).unwrap();
+        }
+        assert!(sys.command("retract 1").is_err());
+        assert_eq!(sys.retract(4).unwrap(), vec![4, 6]);
+        assert_eq!(sys.proofs.len(), 6);
+        assert!(sys.contexts.is_empty());
+        assert_eq!(sys.proofs[5].rule, Rule::D(0, 1));
+        sys.command("retract 3").unwrap();
+        assert_eq!(sys.proofs[4].rule, Rule::D(0, 1));
+        sys.command("undo").unwrap();
+        assert_eq!(sys.proofs.len(), 6);
+        sys.command("undo").unwrap();
+        assert_eq!(sys.contexts, vec![4]);
+        assert_eq!(sys.proofs[6].rule, Rule::Mp(4, 5));
+        sys.command("redo").unwrap();
+        assert_eq!(sys.proofs.len(), 6);
+        sys.command("inst 0 a := c").unwrap();
+        assert!(sys.command("redo").is_err());
+    }
+
+    #[test]
+    fn script() {
+        let file_name = std::env::temp_dir().join("logic_script.txt");
+        let file_name = file_name.to_str().unwrap();
+        std::fs::write(file_name, "# a -> a\nD 0 1\n\nD 0 3\nmp 0 0\nD 0 0\n").unwrap();
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        let err = crate::util::run_script(file_name, |line| sys.command(line)).unwrap_err();
+        assert!(err.starts_with(&format!("{}:5: mp 0 0: ", file_name)));
+        assert_eq!(sys.proofs.len(), 5);
+        assert_eq!(sys.proofs[4].form, sys.parser.parse("a -> a").unwrap());
+    }
+
+    #[test]
+    fn load_checks_steps() {
+        let file_name = std::env::temp_dir().join("logic_load_checks_steps.txt");
+        let file_name = file_name.to_str().unwrap();
+        let mut sys = System::new("ops.txt", "prop_axioms.txt").unwrap();
+        sys.command("inst 0 b b -> a").unwrap();
+        sys.save(file_name).unwrap();
+        sys.load(file_name).unwrap();
+        assert_eq!(sys.proofs.len(), 4);
+
+        std::fs::write(file_name, "0: a -> b -> a [axiom]\n1: F [axiom]\n").unwrap();
+        let err = sys.load(file_name).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: not an axiom");
+        assert_eq!(sys.proofs.len(), 4);
+
+        std::fs::write(file_name, "0: a -> b -> a [axiom]\n1: a -> a [inst 0 b a]\n").unwrap();
+        let err = sys.load(file_name).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: the rule derives a -> a -> a");
+
+        // A constant is not a variable and cannot be substituted.
+        assert!(sys.command("inst 2 F := b").is_err());
+        std::fs::write(file_name, "0: a -> b -> a [axiom]\n1: (a -> b -> c) -> (a -> b) -> a -> c [axiom]\n\
+            2: ((a -> F) -> F) -> a [axiom]\n3: ((a -> b) -> b) -> a [inst 2 F := b]\n").unwrap();
+        let err = sys.load(file_name).unwrap_err();
+        assert_eq!(err.to_string(), "line 4: F is not a variable");
+
+        std::fs::write(file_name, "").unwrap();
+        sys.load(file_name).unwrap();
+        assert!(sys.command("discharge").is_err());
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
@@ -0,0 +1,450 @@
+//! Refutation by resolution for first-order formulas.
+//!
+//! Formulas are put in negation normal form, their quantifiers moved to the front in the
+//! order they occur and the existential ones replaced by Skolem terms `sk0(@x, ...)`, and
+//! the remaining matrix distributed into clauses. The variables of a clause are written
+//! `@x` like pattern variables. The clauses are saturated by binary resolution and
+//! factoring with given-clause selection by weight, deleting tautologies and subsumed
+//! clauses, until the empty clause is derived. `=` is an uninterpreted predicate.
+
+use tree::v3::{Tree, Subtree};
+use parser::parser::{substitute_tree, unify_tree};
+use parser::sym::SymDB;
+use std::collections::{HashMap, HashSet};
+
+/// The default bound on the number of given clauses.
+pub const MAX_GIVEN: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    pub positive: bool,
+    pub atom: Tree<String>,
+}
+
+/// How a clause was obtained.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Input,
+    Resolve(usize, usize),
+    Factor(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Clause {
+    pub lits: Vec<Literal>,
+    pub source: Source,
+}
+
+/// All clauses kept by the prover, one of which is empty.
+#[derive(Debug)]
+pub struct Refutation {
+    pub clauses: Vec<Clause>,
+    pub empty: usize,
+}
+
+impl Refutation {
+    /// IDs of the clauses the empty clause was derived from, including itself, in ascending order.
+    pub fn trace(&self) -> Vec<usize> {
+        let mut used = vec![false; self.empty + 1];
+        used[self.empty] = true;
+        for i in (0..=self.empty).rev() {
+            if !used[i] {
+                continue;
+            }
+            match self.clauses[i].source {
+                Source::Input => {}
+                Source::Resolve(id1, id2) => {
+                    used[id1] = true;
+                    used[id2] = true;
+                }
+                Source::Factor(id) => used[id] = true,
+            }
+        }
+        (0..=self.empty).filter(|&i| used[i]).collect()
+    }
+}
+
+fn leaf(name: &str) -> Tree<String> {
+    Tree::new(name.to_string())
+}
+
+fn node(op: &str, args: Vec<Tree<String>>) -> Tree<String> {
+    let mut tree = leaf(op);
+    for arg in args {
+        tree.push_tree(tree.root_id(), arg);
+    }
+    tree
+}
+
+fn is_var(name: &str) -> bool {
+    name.starts_with('@')
+}
+
+/// Extends `subst` so that `pat` with `subst` applied is `target`.
+fn match_term(pat: Subtree<'_, String>, target: Subtree<'_, String>, subst: &mut HashMap<String, Tree<String>>) -> bool {
+    let value = pat.get_root();
+    if pat.is_leaf() && is_var(value) {
+        if let Some(bound) = subst.get(value) {
+            return bound.subtree_root() == target;
+        }
+        subst.insert(value.clone(), target.to_owned());
+        return true;
+    }
+    value == target.get_root() && pat.children_len() == target.children_len()
+        && pat.children_root().zip(target.children_root()).all(|(arg, target_arg)| match_term(arg, target_arg, subst))
+}
+
+/// Whether `lits` with some substitution `subst` extends is a subset of `target`.
+fn subsumes_sub(lits: &[Literal], target: &[Literal], subst: &HashMap<String, Tree<String>>) -> bool {
+    let Some((first, rest)) = lits.split_first() else { return true; };
+    target.iter().any(|lit| {
+        let mut subst = subst.clone();
+        lit.positive == first.positive
+            && match_term(first.atom.subtree_root(), lit.atom.subtree_root(), &mut subst)
+            && subsumes_sub(rest, target, &subst)
+    })
+}
+
+fn subsumes(lits: &[Literal], target: &[Literal]) -> bool {
+    lits.len() <= target.len() && subsumes_sub(lits, target, &HashMap::new())
+}
+
+fn variables(lits: &[Literal]) -> HashSet<String> {
+    lits.iter()
+        .flat_map(|lit| lit.atom.ids().map(move |id| lit.atom.get(id).unwrap()))
+        .filter(|name| is_var(name))
+        .cloned()
+        .collect()
+}
+
+/// Renames the variables of `lits` that also occur in `avoid`.
+fn rename_apart(lits: &[Literal], avoid: &[Literal]) -> Vec<Literal> {
+    let avoid = variables(avoid);
+    let mut used: HashSet<String> = avoid.union(&variables(lits)).cloned().collect();
+    let mut subst = HashMap::new();
+    for var in variables(lits) {
+        if avoid.contains(&var) {
+            let new_var = (1..).map(|i| format!("{}{}", var, i)).find(|name| !used.contains(name)).unwrap();
+            used.insert(new_var.clone());
+            subst.insert(var, leaf(&new_var));
+        }
+    }
+    substitute(lits, &subst)
+}
+
+fn substitute(lits: &[Literal], subst: &HashMap<String, Tree<String>>) -> Vec<Literal> {
+    lits.iter()
+        .map(|lit| Literal { positive: lit.positive, atom: substitute_tree(lit.atom.subtree_root(), subst) })
+        .collect()
+}
+
+/// Removes duplicate literals, or returns `None` if `lits` contains complementary literals.
+fn simplify(lits: Vec<Literal>) -> Option<Vec<Literal>> {
+    let mut result: Vec<Literal> = Vec::with_capacity(lits.len());
+    for lit in lits {
+        if result.iter().any(|other| other.atom == lit.atom && other.positive != lit.positive) {
+            return None;
+        }
+        if !result.contains(&lit) {
+            result.push(lit);
+        }
+    }
+    Some(result)
+}
+
+fn weight(lits: &[Literal]) -> usize {
+    lits.iter().map(|lit| lit.atom.ids().count()).sum()
+}
+
+/// Converts `form` to negation normal form, negated if `positive` is false.
+fn nnf(form: Subtree<'_, String>, positive: bool) -> Tree<String> {
+    let value = form.get_root();
+    let mut args = form.children_root();
+    let dual = |op: &str, dual_op: &str| if positive { op.to_string() } else { dual_op.to_string() };
+    match value.as_str() {
+        "T" | "F" if form.is_leaf() => leaf(&if value == "T" { dual("T", "F") } else { dual("F", "T") }),
+        "!" => nnf(args.next().unwrap(), !positive),
+        "&" | "|" => {
+            let op = if value == "&" { dual("&", "|") } else { dual("|", "&") };
+            node(&op, args.map(|arg| nnf(arg, positive)).collect())
+        }
+        "->" => {
+            let antecedent = nnf(args.next().unwrap(), !positive);
+            let consequent = nnf(args.next().unwrap(), positive);
+            node(&dual("|", "&"), vec![antecedent, consequent])
+        }
+        "∀" | "∃" => {
+            let op = if value == "∀" { dual("∀", "∃") } else { dual("∃", "∀") };
+            let binder = args.next().unwrap().to_owned();
+            node(&op, vec![binder, nnf(args.next().unwrap(), positive)])
+        }
+        _ if positive => form.to_owned(),
+        _ => node("!", vec![form.to_owned()]),
+    }
+}
+
+/// Distributes a quantifier-free formula in negation normal form into clauses.
+fn cnf(form: Subtree<'_, String>) -> Vec<Vec<Literal>> {
+    let mut args = form.children_root();
+    match form.get_root().as_str() {
+        "T" if form.is_leaf() => Vec::new(),
+        "F" if form.is_leaf() => vec![Vec::new()],
+        "&" => args.flat_map(cnf).collect(),
+        "|" => {
+            let lhs = cnf(args.next().unwrap());
+            let rhs = cnf(args.next().unwrap());
+            lhs.iter()
+                .flat_map(|clause_lhs| rhs.iter().map(move |clause_rhs| [clause_lhs.clone(), clause_rhs.clone()].concat()))
+                .collect()
+        }
+        "!" => vec![vec![Literal { positive: false, atom: args.next().unwrap().to_owned() }]],
+        _ => vec![vec![Literal { positive: true, atom: form.to_owned() }]],
+    }
+}
+
+struct Clausifier<'a> {
+    symdb: &'a SymDB,
+    /// Names that Skolem functions and clause variables must avoid.
+    used: HashSet<String>,
+    skolems: usize,
+}
+
+impl Clausifier<'_> {
+    fn fresh(&mut self, base: &str) -> String {
+        let name = if self.used.contains(base) {
+            (0..).map(|i| format!("{}{}", base, i)).find(|name| !self.used.contains(name)).unwrap()
+        } else {
+            base.to_string()
+        };
+        self.used.insert(name.clone());
+        name
+    }
+
+    /// Removes the quantifiers of a formula in negation normal form, replacing the variables
+    /// bound by `∀` with clause variables and those bound by `∃` with Skolem terms whose
+    /// arguments are the universal variables before them. `scope` maps the bound variables
+    /// to their replacements.
+    fn skolemize(&mut self, form: Subtree<'_, String>, scope: &mut HashMap<String, Tree<String>>, universals: &mut Vec<Tree<String>>) -> Tree<String> {
+        let value = form.get_root();
+        let mut args = form.children_root();
+        if value == "∀" || value == "∃" {
+            let binder = args.next().unwrap().get_root().clone();
+            let replacement = if value == "∀" {
+                let var = leaf(&self.fresh(&format!("@{}", binder)));
+                universals.push(var.clone());
+                var
+            } else {
+                let name = loop {
+                    let name = format!("sk{}", self.skolems);
+                    self.skolems += 1;
+                    if !self.used.contains(&name) {
+                        break name;
+                    }
+                };
+                node(&name, universals.clone())
+            };
+            let shadowed = scope.insert(binder.clone(), replacement);
+            let body = self.skolemize(args.next().unwrap(), scope, universals);
+            match shadowed {
+                Some(shadowed) => scope.insert(binder, shadowed),
+                None => scope.remove(&binder),
+            };
+            return body;
+        }
+        if form.is_leaf() {
+            return scope.get(value).cloned().unwrap_or_else(|| leaf(value));
+        }
+        node(value, args.map(|arg| self.skolemize(arg, scope, universals)).collect())
+    }
+
+    fn clausify(&mut self, form: &Tree<String>) -> Result<Vec<Vec<Literal>>, String> {
+        for id in form.ids() {
+            let value = form.get(id).unwrap();
+            if value == "∀" || value == "∃" {
+                let binder = form.get_node(form.children(id).next().unwrap()).unwrap();
+                if !binder.is_leaf() || !self.symdb.is_variable(binder.value()) {
+                    return Err(format!("invalid variable name after {}", value));
+                }
+            }
+        }
+        let form = nnf(form.subtree_root(), true);
+        let matrix = self.skolemize(form.subtree_root(), &mut HashMap::new(), &mut Vec::new());
+        Ok(cnf(matrix.subtree_root()).into_iter().filter_map(simplify).collect())
+    }
+}
+
+struct Prover {
+    clauses: Vec<Clause>,
+    passive: Vec<usize>,
+    active: Vec<usize>,
+}
+
+impl Prover {
+    /// Adds a clause to the passive clauses and returns its ID if it is empty.
+    fn add(&mut self, lits: Vec<Literal>, source: Source) -> Option<usize> {
+        let lits = simplify(lits)?;
+        let id = self.clauses.len();
+        let empty = lits.is_empty();
+        self.clauses.push(Clause { lits, source });
+        self.passive.push(id);
+        empty.then_some(id)
+    }
+
+    /// Derives the factors of `given` and its resolvents with the active clauses.
+    fn infer(&mut self, given: usize) -> Option<usize> {
+        let lits = self.clauses[given].lits.clone();
+        for i in 0..lits.len() {
+            for j in i + 1..lits.len() {
+                if lits[i].positive != lits[j].positive {
+                    continue;
+                }
+                if let Some(subst) = unify_tree(lits[i].atom.subtree_root(), lits[j].atom.subtree_root(), is_var) {
+                    let factor = lits.iter().enumerate()
+                        .filter(|&(k, _)| k != j)
+                        .map(|(_, lit)| lit.clone())
+                        .collect::<Vec<Literal>>();
+                    if let Some(id) = self.add(substitute(&factor, &subst), Source::Factor(given)) {
+                        return Some(id);
+                    }
+                }
+            }
+        }
+        for other in self.active.clone() {
+            let other_lits = rename_apart(&self.clauses[other].lits, &lits);
+            for (i, lit) in lits.iter().enumerate() {
+                for (j, other_lit) in other_lits.iter().enumerate() {
+                    if lit.positive == other_lit.positive {
+                        continue;
+                    }
+                    let Some(subst) = unify_tree(lit.atom.subtree_root(), other_lit.atom.subtree_root(), is_var) else {
+                        continue;
+                    };
+                    let resolvent = lits.iter().enumerate().filter(|&(k, _)| k != i)
+                        .chain(other_lits.iter().enumerate().filter(|&(k, _)| k != j))
+                        .map(|(_, lit)| lit.clone())
+                        .collect::<Vec<Literal>>();
+                    if let Some(id) = self.add(substitute(&resolvent, &subst), Source::Resolve(given, other)) {
+                        return Some(id);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Searches for a refutation of the conjunction of `forms` with at most `max_given` given clauses.
+/// The free variables of `forms` are constants.
+pub fn refute(forms: &[Tree<String>], symdb: &SymDB, max_given: usize) -> Result<Refutation, String> {
+    let used = forms.iter()
+        .flat_map(|form| form.ids().map(move |id| form.get(id).unwrap().clone()))
+        .collect();
+    let mut clausifier = Clausifier { symdb, used, skolems: 0 };
+    let mut prover = Prover { clauses: Vec::new(), passive: Vec::new(), active: Vec::new() };
+    for form in forms {
+        for lits in clausifier.clausify(form)? {
+            if let Some(empty) = prover.add(lits, Source::Input) {
+                return Ok(Refutation { clauses: prover.clauses, empty });
+            }
+        }
+    }
+    for _ in 0..max_given {
+        let Some(i) = (0..prover.passive.len())
+            .min_by_key(|&i| (weight(&prover.clauses[prover.passive[i]].lits), prover.passive[i])) else {
+            return Err("saturated without the empty clause: not provable".to_string());
+        };
+        let given = prover.passive.remove(i);
+        let lits = &prover.clauses[given].lits;
+        if prover.active.iter().any(|&id| subsumes(&prover.clauses[id].lits, lits)) {
+            continue;
+        }
+        let clauses = &prover.clauses;
+        prover.active.retain(|&id| !subsumes(lits, &clauses[id].lits));
+        prover.active.push(given);
+        if let Some(empty) = prover.infer(given) {
+            return Ok(Refutation { clauses: prover.clauses, empty });
+        }
+    }
+    Err(format!("no refutation found with {} given clauses", max_given))
+}
+
+/// Searches for a refutation of the negation of `goal` under the hypotheses `hyps`.
+pub fn prove(hyps: &[Tree<String>], goal: &Tree<String>, symdb: &SymDB, max_given: usize) -> Result<Refutation, String> {
+    let mut forms = hyps.to_vec();
+    forms.push(node("!", vec![goal.clone()]));
+    refute(&forms, symdb, max_given)
+}
+
+/// Formats `form`, writing the Skolem terms as `sk0(@x, ...)`.
+fn to_string(form: &Tree<String>, symdb: &SymDB) -> String {
+    fn replace_skolem(form: Subtree<'_, String>, symdb: &SymDB) -> Tree<String> {
+        let value = form.get_root();
+        if form.is_leaf() {
+            return leaf(value);
+        }
+        if symdb.is_variable(value) {
+            let args: Vec<String> = form.children_root()
+                .map(|arg| to_string(&arg.to_owned(), symdb))
+                .collect();
+            return leaf(&format!("{}({})", value, args.join(", ")));
+        }
+        node(value, form.children_root().map(|arg| replace_skolem(arg, symdb)).collect())
+    }
+    symdb.ast_to_string_minimal(&replace_skolem(form.subtree_root(), symdb))
+}
+
+/// Formats a clause as a disjunction of literals, or `F` if it is empty.
+pub fn clause_to_string(lits: &[Literal], symdb: &SymDB) -> String {
+    let form = lits.iter()
+        .map(|lit| if lit.positive { lit.atom.clone() } else { node("!", vec![lit.atom.clone()]) })
+        .reduce(|lhs, rhs| node("|", vec![lhs, rhs]))
+        .unwrap_or_else(|| leaf("F"));
+    to_string(&form, symdb)
+}
+
+/// Formats the clauses of the trace as `ID: clause [source]`.
+pub fn refutation_to_string(refutation: &Refutation, symdb: &SymDB) -> String {
+    let mut s = String::new();
+    for id in refutation.trace() {
+        let clause = &refutation.clauses[id];
+        let source = match clause.source {
+            Source::Input => "input".to_string(),
+            Source::Resolve(id1, id2) => format!("resolve {} {}", id1, id2),
+            Source::Factor(id) => format!("factor {}", id),
+        };
+        s += &format!("{}: {} [{}]\n", id, clause_to_string(&clause.lits, symdb), source);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    #[test]
+    fn refutation() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let form = parser.parse("(x ∀ y ∃ x = y) & !(y ∀ y = c)").unwrap();
+        let mut clausifier = Clausifier { symdb: parser.symdb(), used: HashSet::new(), skolems: 0 };
+        let clauses = clausifier.clausify(&form).unwrap();
+        let clauses: Vec<String> = clauses.iter().map(|lits| clause_to_string(lits, parser.symdb())).collect();
+        assert_eq!(clauses, ["@x = sk0(@x)", "! sk1(@x) = c"]);
+
+        let hyps = [
+            parser.parse("x ∀ (x = a -> x = b)").unwrap(),
+            parser.parse("x ∀ (x = b -> x = c)").unwrap(),
+        ];
+        let goal = parser.parse("x ∀ (x = a -> x = c)").unwrap();
+        let refutation = prove(&hyps, &goal, parser.symdb(), MAX_GIVEN).unwrap();
+        assert!(refutation.clauses[refutation.empty].lits.is_empty());
+        let trace = refutation_to_string(&refutation, parser.symdb());
+        assert!(trace.starts_with("0: ! @x = a | @x = b [input]\n"));
+
+        let goal = parser.parse("(x ∃ y ∀ x = y) -> (y ∀ x ∃ x = y)").unwrap();
+        assert!(prove(&[], &goal, parser.symdb(), MAX_GIVEN).is_ok());
+        let goal = parser.parse("(x ∀ y ∃ x = y) -> (y ∃ x ∀ x = y)").unwrap();
+        assert_eq!(prove(&[], &goal, parser.symdb(), MAX_GIVEN).unwrap_err(),
+            "saturated without the empty clause: not provable");
+    }
+}
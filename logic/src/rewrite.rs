@@ -0,0 +1,189 @@
+//! Term rewriting with oriented rules `lhs => rhs`.
+//!
+//! A rule rewrites an instance of its left side, with `@a` matching any term and `_` anything,
+//! into the corresponding instance of its right side. `normalize` repeats `step` by the innermost
+//! or outermost strategy until no rule applies or the step limit is reached.
+
+use tree::v3::{Tree, Subtree};
+use parser::parser::{Parser, pattern_match_tree, substitute_tree};
+use parser::sym::SymDB;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Default limit of rewrite steps in `normalize`.
+pub const MAX_STEPS: usize = 1000;
+
+/// An oriented rule `lhs => rhs`. Variables are written `@a` and `_` matches anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RewriteRule {
+    pub lhs: Tree<String>,
+    pub rhs: Tree<String>,
+}
+
+/// Which redex `step` rewrites: the leftmost of the innermost ones or of the outermost ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Innermost,
+    Outermost,
+}
+
+/// A rewrite step, by the rule with the index `rule`, resulting in `term`.
+pub struct Step {
+    pub rule: usize,
+    pub term: Tree<String>,
+}
+
+pub struct Trace {
+    pub start: Tree<String>,
+    pub steps: Vec<Step>,
+    /// Whether the last term is a normal form, that is, the step limit was not reached.
+    pub normal: bool,
+}
+
+impl Trace {
+    pub fn last(&self) -> &Tree<String> {
+        self.steps.last().map_or(&self.start, |step| &step.term)
+    }
+}
+
+fn variables(form: &Tree<String>) -> Vec<String> {
+    let mut vars: Vec<String> = Vec::new();
+    for id in form.ids() {
+        let value = form.get(id).unwrap();
+        if value.starts_with('@') && !vars.contains(value) {
+            vars.push(value.clone());
+        }
+    }
+    vars
+}
+
+/// Parses a rule `lhs => rhs`, whose right side has no variables other than those on the left side.
+pub fn parse_rule(parser: &mut Parser, s: &str) -> Result<RewriteRule, String> {
+    let Some((lhs, rhs)) = s.split_once("=>") else {
+        return Err(format!("expected '[lhs] => [rhs]': {}", s.trim()));
+    };
+    let lhs = parser.parse(lhs)?;
+    let rhs = parser.parse(rhs)?;
+    let root = lhs.get_root().unwrap();
+    if root.starts_with('@') || root == "_" {
+        return Err("the left side is a variable".to_string());
+    }
+    let vars_lhs = variables(&lhs);
+    if let Some(var) = variables(&rhs).into_iter().find(|var| !vars_lhs.contains(var)) {
+        return Err(format!("{} does not occur on the left side", var));
+    }
+    if rhs.ids().any(|id| rhs.get(id).unwrap() == "_") {
+        return Err("_ occurs on the right side".to_string());
+    }
+    Ok(RewriteRule { lhs, rhs })
+}
+
+/// Loads rules, one per line. Empty lines are skipped.
+pub fn load_rules(parser: &mut Parser, file_name: &str) -> Result<Vec<RewriteRule>, String> {
+    let reader = BufReader::new(File::open(file_name).map_err(|err| err.to_string())?);
+    let mut rules = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        rules.push(parse_rule(parser, &line).map_err(|err| format!("line {}: {}", i + 1, err))?);
+    }
+    Ok(rules)
+}
+
+pub fn rule_to_string(rule: &RewriteRule, symdb: &SymDB) -> String {
+    format!("{} => {}", symdb.ast_to_string_minimal(&rule.lhs), symdb.ast_to_string_minimal(&rule.rhs))
+}
+
+/// Rewrites `term` at the root by the first matching rule.
+fn rewrite_root(term: Subtree<String>, rules: &[RewriteRule]) -> Option<(usize, Tree<String>)> {
+    rules.iter().enumerate().find_map(|(i, rule)| {
+        let map = pattern_match_tree(term, rule.lhs.subtree_root()).ok()?;
+        let subst: HashMap<String, Tree<String>> = map.into_iter()
+            .map(|(var, tree)| (format!("@{}", var), tree))
+            .collect();
+        Some((i, substitute_tree(rule.rhs.subtree_root(), &subst)))
+    })
+}
+
+/// Rewrites one redex of `term` chosen by `strategy`.
+/// Returns the index of the rule and the new term, or `None` if `term` is a normal form.
+pub fn step(term: Subtree<String>, rules: &[RewriteRule], strategy: Strategy) -> Option<(usize, Tree<String>)> {
+    if strategy == Strategy::Outermost {
+        if let Some(result) = rewrite_root(term, rules) {
+            return Some(result);
+        }
+    }
+    let children: Vec<Subtree<String>> = term.children_root().collect();
+    for (i, child) in children.iter().enumerate() {
+        let Some((rule, new_child)) = step(*child, rules, strategy) else {
+            continue;
+        };
+        let mut new_term = Tree::new(term.get_root().clone());
+        for (j, child) in children.iter().enumerate() {
+            if i == j {
+                new_term.push_tree(new_term.root_id(), new_child.clone());
+            } else {
+                new_term.push_sub(new_term.root_id(), *child);
+            }
+        }
+        return Some((rule, new_term));
+    }
+    if strategy == Strategy::Innermost {
+        return rewrite_root(term, rules);
+    }
+    None
+}
+
+/// Rewrites `term` until it is a normal form or `max_steps` steps are taken.
+pub fn normalize(term: &Tree<String>, rules: &[RewriteRule], strategy: Strategy, max_steps: usize) -> Trace {
+    let mut trace = Trace { start: term.clone(), steps: Vec::new(), normal: false };
+    while trace.steps.len() < max_steps {
+        let Some((rule, term)) = step(trace.last().subtree_root(), rules, strategy) else {
+            trace.normal = true;
+            return trace;
+        };
+        trace.steps.push(Step { rule, term });
+    }
+    trace.normal = step(trace.last().subtree_root(), rules, strategy).is_none();
+    trace
+}
+
+/// Formats a trace as `0: term`, followed by `N: term [rule]` for each step.
+pub fn trace_to_string(trace: &Trace, rules: &[RewriteRule], symdb: &SymDB) -> String {
+    let mut s = format!("0: {}\n", symdb.ast_to_string_minimal(&trace.start));
+    for (i, step) in trace.steps.iter().enumerate() {
+        s += &format!("{}: {} [{}]\n", i + 1, symdb.ast_to_string_minimal(&step.term), rule_to_string(&rules[step.rule], symdb));
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategies() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let rules = load_rules(&mut parser, "arith_rules.txt").unwrap();
+        let term = parser.parse("(a + 0) * (b ^ 1 + 0 * c)").unwrap();
+        let expected = parser.parse("a * b").unwrap();
+        for strategy in [Strategy::Innermost, Strategy::Outermost] {
+            let trace = normalize(&term, &rules, strategy, MAX_STEPS);
+            assert!(trace.normal);
+            assert_eq!(trace.last(), &expected);
+        }
+        let trace = normalize(&term, &rules, Strategy::Innermost, MAX_STEPS);
+        let lines: Vec<String> = trace_to_string(&trace, &rules, parser.symdb()).lines().map(String::from).collect();
+        assert_eq!(lines[1], "1: a * (b ^ 1 + 0 * c) [@a + 0 => @a]");
+        let commutative = vec![parse_rule(&mut parser, "@a + @b => @b + @a").unwrap()];
+        let trace = normalize(&expected, &commutative, Strategy::Innermost, 10);
+        assert!(trace.normal);
+        let trace = normalize(&parser.parse("a + b").unwrap(), &commutative, Strategy::Innermost, 10);
+        assert!(!trace.normal);
+        assert_eq!(trace.steps.len(), 10);
+        assert!(parse_rule(&mut parser, "@a + 0 => @b").is_err());
+    }
+}
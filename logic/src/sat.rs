@@ -0,0 +1,414 @@
+//! A CDCL SAT solver and the Tseitin encoding of parsed formulas.
+
+use tree::v3::Subtree;
+use parser::sym::SymDB;
+use crate::semantics;
+use std::collections::HashMap;
+use std::ops::Not;
+
+/// A formula in conjunctive normal form.
+/// Literals are nonzero integers as in DIMACS: `v` for the variable `v` and `-v` for its negation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cnf {
+    pub num_vars: usize,
+    pub clauses: Vec<Vec<i32>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lit(u32);
+
+impl Lit {
+    fn from_dimacs(lit: i32) -> Self {
+        let var = lit.unsigned_abs() - 1;
+        Lit(var << 1 | (lit < 0) as u32)
+    }
+    #[inline]
+    fn var(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+    #[inline]
+    fn is_neg(self) -> bool {
+        self.0 & 1 == 1
+    }
+    #[inline]
+    fn code(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Not for Lit {
+    type Output = Self;
+    fn not(self) -> Self {
+        Lit(self.0 ^ 1)
+    }
+}
+
+#[inline]
+fn lit_value(values: &[Option<bool>], lit: Lit) -> Option<bool> {
+    values[lit.var()].map(|value| value != lit.is_neg())
+}
+
+pub struct Solver {
+    num_vars: usize,
+    clauses: Vec<Vec<Lit>>,
+    /// Clauses watching each literal, indexed by `Lit::code`.
+    watches: Vec<Vec<usize>>,
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    /// Start of each decision level in `trail`.
+    trail_lim: Vec<usize>,
+    qhead: usize,
+    activity: Vec<f64>,
+    var_inc: f64,
+    phases: Vec<bool>,
+    /// Unit clauses waiting to be assigned at level 0.
+    units: Vec<Lit>,
+    unsat: bool,
+}
+
+impl Solver {
+    pub fn new(num_vars: usize) -> Self {
+        Self {
+            num_vars,
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); 2 * num_vars],
+            values: vec![None; num_vars],
+            levels: vec![0; num_vars],
+            reasons: vec![None; num_vars],
+            trail: Vec::new(),
+            trail_lim: Vec::new(),
+            qhead: 0,
+            activity: vec![0.0; num_vars],
+            var_inc: 1.0,
+            phases: vec![false; num_vars],
+            units: Vec::new(),
+            unsat: false,
+        }
+    }
+
+    pub fn from_cnf(cnf: &Cnf) -> Result<Self, String> {
+        let mut solver = Self::new(cnf.num_vars);
+        for clause in cnf.clauses.iter() {
+            solver.add_clause(clause)?;
+        }
+        Ok(solver)
+    }
+
+    /// Adds a clause of DIMACS literals.
+    pub fn add_clause(&mut self, clause: &[i32]) -> Result<(), String> {
+        let mut lits: Vec<Lit> = Vec::with_capacity(clause.len());
+        for &lit in clause {
+            if lit == 0 || lit.unsigned_abs() as usize > self.num_vars {
+                return Err(format!("invalid literal: {}", lit));
+            }
+            let lit = Lit::from_dimacs(lit);
+            if lits.contains(&!lit) {
+                return Ok(());
+            }
+            if !lits.contains(&lit) {
+                lits.push(lit);
+            }
+        }
+        match lits.len() {
+            0 => self.unsat = true,
+            1 => self.units.push(lits[0]),
+            _ => {
+                let id = self.clauses.len();
+                self.watches[lits[0].code()].push(id);
+                self.watches[lits[1].code()].push(id);
+                self.clauses.push(lits);
+            }
+        }
+        Ok(())
+    }
+
+    fn level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.values[var] = Some(!lit.is_neg());
+        self.levels[var] = self.level();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Propagates the assignments in `trail` and returns a conflicting clause if any.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.qhead < self.trail.len() {
+            let false_lit = !self.trail[self.qhead];
+            self.qhead += 1;
+            let watchers = std::mem::take(&mut self.watches[false_lit.code()]);
+            let mut kept = Vec::with_capacity(watchers.len());
+            for (i, &id) in watchers.iter().enumerate() {
+                let clause = &mut self.clauses[id];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                if lit_value(&self.values, clause[0]) == Some(true) {
+                    kept.push(id);
+                    continue;
+                }
+                if let Some(k) = (2..clause.len()).find(|&k| lit_value(&self.values, clause[k]) != Some(false)) {
+                    clause.swap(1, k);
+                    self.watches[clause[1].code()].push(id);
+                    continue;
+                }
+                kept.push(id);
+                let first = clause[0];
+                if lit_value(&self.values, first) == Some(false) {
+                    kept.extend_from_slice(&watchers[i + 1..]);
+                    self.watches[false_lit.code()] = kept;
+                    self.qhead = self.trail.len();
+                    return Some(id);
+                }
+                self.enqueue(first, Some(id));
+            }
+            self.watches[false_lit.code()] = kept;
+        }
+        None
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.var_inc;
+        if self.activity[var] > 1e100 {
+            for activity in self.activity.iter_mut() {
+                *activity *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+    }
+
+    /// Derives a clause by first-UIP learning and returns it with the level to backtrack to.
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let mut seen = vec![false; self.num_vars];
+        let mut learnt = vec![Lit(0)];
+        let mut counter = 0;
+        let mut pivot = None;
+        let mut idx = self.trail.len();
+        let mut clause = conflict;
+        loop {
+            for k in 0..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                if Some(lit) == pivot {
+                    continue;
+                }
+                let var = lit.var();
+                if seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+                seen[var] = true;
+                self.bump(var);
+                if self.levels[var] == self.level() {
+                    counter += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+            loop {
+                idx -= 1;
+                if seen[self.trail[idx].var()] {
+                    break;
+                }
+            }
+            let lit = self.trail[idx];
+            seen[lit.var()] = false;
+            pivot = Some(lit);
+            counter -= 1;
+            if counter == 0 {
+                break;
+            }
+            clause = self.reasons[lit.var()].unwrap();
+        }
+        learnt[0] = !pivot.unwrap();
+        let mut back_level = 0;
+        for k in 1..learnt.len() {
+            let level = self.levels[learnt[k].var()];
+            if level > back_level {
+                back_level = level;
+                learnt.swap(1, k);
+            }
+        }
+        (learnt, back_level)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.level() <= level {
+            return;
+        }
+        let start = self.trail_lim[level];
+        for lit in self.trail.drain(start..) {
+            let var = lit.var();
+            self.phases[var] = !lit.is_neg();
+            self.values[var] = None;
+            self.reasons[var] = None;
+        }
+        self.trail_lim.truncate(level);
+        self.qhead = self.trail.len();
+    }
+
+    fn pick_branch(&self) -> Option<Lit> {
+        let var = (0..self.num_vars)
+            .filter(|&var| self.values[var].is_none())
+            .max_by(|&v0, &v1| self.activity[v0].total_cmp(&self.activity[v1]))?;
+        Some(Lit((var as u32) << 1 | !self.phases[var] as u32))
+    }
+
+    /// Returns a model, indexed by variable number minus one, or `None` if unsatisfiable.
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        if self.unsat {
+            return None;
+        }
+        self.backtrack(0);
+        for lit in std::mem::take(&mut self.units) {
+            match lit_value(&self.values, lit) {
+                Some(true) => {}
+                Some(false) => {
+                    self.unsat = true;
+                    return None;
+                }
+                None => self.enqueue(lit, None),
+            }
+        }
+        let mut conflicts = 0;
+        let mut restart_limit = 100.0;
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.level() == 0 {
+                    self.unsat = true;
+                    return None;
+                }
+                let (learnt, back_level) = self.analyze(conflict);
+                self.backtrack(back_level);
+                if learnt.len() == 1 {
+                    self.enqueue(learnt[0], None);
+                } else {
+                    let id = self.clauses.len();
+                    self.watches[learnt[0].code()].push(id);
+                    self.watches[learnt[1].code()].push(id);
+                    let first = learnt[0];
+                    self.clauses.push(learnt);
+                    self.enqueue(first, Some(id));
+                }
+                self.var_inc /= 0.95;
+                conflicts += 1;
+                if conflicts as f64 >= restart_limit {
+                    conflicts = 0;
+                    restart_limit *= 1.5;
+                    self.backtrack(0);
+                }
+                continue;
+            }
+            let Some(lit) = self.pick_branch() else {
+                let model = self.values.iter().map(|value| value.unwrap()).collect();
+                self.backtrack(0);
+                return Some(model);
+            };
+            self.trail_lim.push(self.trail.len());
+            self.enqueue(lit, None);
+        }
+    }
+}
+
+/// Encodes `form` built from `T`, `F`, `!`, `&`, `|` and `->` into an equisatisfiable `Cnf`.
+/// The variables of `form` are returned in order and numbered from 1;
+/// auxiliary variables follow them.
+pub fn tseitin(form: Subtree<'_, String>, symdb: &SymDB) -> Result<(Cnf, Vec<String>), String> {
+    let names = semantics::variables(form, symdb);
+    let mut cnf = Cnf { num_vars: names.len(), clauses: Vec::new() };
+    let vars: HashMap<&str, i32> = names.iter().enumerate()
+        .map(|(i, name)| (name.as_str(), i as i32 + 1))
+        .collect();
+    let root = tseitin_sub(form, &vars, &mut cnf)?;
+    cnf.clauses.push(vec![root]);
+    Ok((cnf, names))
+}
+
+fn tseitin_sub(form: Subtree<'_, String>, vars: &HashMap<&str, i32>, cnf: &mut Cnf) -> Result<i32, String> {
+    let mut args = form.children_root();
+    let mut next_arg = |cnf: &mut Cnf| -> Result<i32, String> {
+        let arg = args.next().ok_or_else(|| format!("missing operand: {}", form.get_root()))?;
+        tseitin_sub(arg, vars, cnf)
+    };
+    let op = form.get_root().as_str();
+    let (lhs, rhs) = match op {
+        "!" => return Ok(-next_arg(cnf)?),
+        "&" | "|" | "->" => (next_arg(cnf)?, next_arg(cnf)?),
+        "T" | "F" => (0, 0),
+        var => match vars.get(var) {
+            Some(&lit) if form.is_leaf() => return Ok(lit),
+            _ => return Err(format!("cannot encode: {}", var)),
+        }
+    };
+    cnf.num_vars += 1;
+    let x = cnf.num_vars as i32;
+    match op {
+        "T" => cnf.clauses.push(vec![x]),
+        "F" => cnf.clauses.push(vec![-x]),
+        "&" => {
+            cnf.clauses.push(vec![-x, lhs]);
+            cnf.clauses.push(vec![-x, rhs]);
+            cnf.clauses.push(vec![x, -lhs, -rhs]);
+        }
+        _ => {
+            // `a -> b` is encoded as `!a | b`.
+            let lhs = if op == "->" { -lhs } else { lhs };
+            cnf.clauses.push(vec![-x, lhs, rhs]);
+            cnf.clauses.push(vec![x, -lhs]);
+            cnf.clauses.push(vec![x, -rhs]);
+        }
+    }
+    Ok(x)
+}
+
+/// Decides the satisfiability of `form` and returns a satisfying assignment if any.
+pub fn solve(form: Subtree<'_, String>, symdb: &SymDB) -> Result<Option<HashMap<String, bool>>, String> {
+    let (cnf, names) = tseitin(form, symdb)?;
+    let model = Solver::from_cnf(&cnf)?.solve();
+    Ok(model.map(|model| std::iter::zip(names, model).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    fn pigeonhole(holes: i32) -> Cnf {
+        let pigeons = holes + 1;
+        let var = |p: i32, h: i32| p * holes + h + 1;
+        let mut clauses: Vec<Vec<i32>> = (0..pigeons)
+            .map(|p| (0..holes).map(|h| var(p, h)).collect())
+            .collect();
+        for h in 0..holes {
+            for p0 in 0..pigeons {
+                for p1 in p0 + 1..pigeons {
+                    clauses.push(vec![-var(p0, h), -var(p1, h)]);
+                }
+            }
+        }
+        Cnf { num_vars: (pigeons * holes) as usize, clauses }
+    }
+
+    #[test]
+    fn cdcl() {
+        assert_eq!(Solver::from_cnf(&pigeonhole(5)).unwrap().solve(), None);
+        let mut cnf = pigeonhole(5);
+        cnf.clauses.remove(0);
+        let model = Solver::from_cnf(&cnf).unwrap().solve().unwrap();
+        assert!(cnf.clauses.iter().all(|clause| clause.iter()
+            .any(|&lit| model[lit.unsigned_abs() as usize - 1] == (lit > 0))));
+    }
+
+    #[test]
+    fn formulas() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let form = parser.parse("(a | b) & (a -> c) & (b -> c) & !c").unwrap();
+        assert_eq!(solve(form.subtree_root(), parser.symdb()), Ok(None));
+        let form = parser.parse("(a | b) & !(a & T) & (F | !c)").unwrap();
+        let model = solve(form.subtree_root(), parser.symdb()).unwrap().unwrap();
+        assert_eq!(semantics::eval(form.subtree_root(), &model), Ok(true));
+    }
+}
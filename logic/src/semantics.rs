@@ -0,0 +1,108 @@
+//! Truth-table semantics of propositional formulas.
+
+use tree::v3::Subtree;
+use parser::sym::SymDB;
+use std::collections::HashMap;
+
+/// Evaluates `form` built from `T`, `F`, `!`, `&`, `|`, `->` and the variables in `assign`.
+pub fn eval(form: Subtree<'_, String>, assign: &HashMap<String, bool>) -> Result<bool, String> {
+    let mut args = form.children_root();
+    let mut next_arg = || -> Result<bool, String> {
+        let arg = args.next().ok_or_else(|| format!("missing operand: {}", form.get_root()))?;
+        eval(arg, assign)
+    };
+    let value = match form.get_root().as_str() {
+        "T" => true,
+        "F" => false,
+        "!" => !next_arg()?,
+        "&" => {
+            let lhs = next_arg()?;
+            let rhs = next_arg()?;
+            lhs && rhs
+        }
+        "|" => {
+            let lhs = next_arg()?;
+            let rhs = next_arg()?;
+            lhs || rhs
+        }
+        "->" => {
+            let antecedent = next_arg()?;
+            let consequent = next_arg()?;
+            !antecedent || consequent
+        }
+        var => match assign.get(var) {
+            Some(&value) if form.is_leaf() => value,
+            _ => return Err(format!("cannot evaluate: {}", var)),
+        }
+    };
+    Ok(value)
+}
+
+/// Returns the variables of `form` in order of appearance.
+pub fn variables(form: Subtree<'_, String>, symdb: &SymDB) -> Vec<String> {
+    let mut vars = Vec::new();
+    variables_sub(form, symdb, &mut vars);
+    vars
+}
+
+fn variables_sub(form: Subtree<'_, String>, symdb: &SymDB, vars: &mut Vec<String>) {
+    let value = form.get_root();
+    if form.is_leaf() && symdb.is_variable(value) && !vars.contains(value) {
+        vars.push(value.clone());
+    }
+    for arg in form.children_root() {
+        variables_sub(arg, symdb, vars);
+    }
+}
+
+/// The most variables `tautology` enumerates the 2^n assignments of.
+pub const MAX_VARIABLES: usize = 20;
+
+/// Checks `form` against every assignment to its variables.
+/// Returns `None` if `form` is a tautology and a falsifying assignment otherwise.
+pub fn tautology(form: Subtree<'_, String>, symdb: &SymDB) -> Result<Option<HashMap<String, bool>>, String> {
+    let vars = variables(form, symdb);
+    if vars.len() > MAX_VARIABLES {
+        return Err(format!("too many variables for a truth table: {} (at most {}); use sat",
+            vars.len(), MAX_VARIABLES));
+    }
+    for bits in 0..1usize << vars.len() {
+        let assign: HashMap<String, bool> = vars.iter().enumerate()
+            .map(|(i, var)| (var.clone(), bits & 1 << i == 0))
+            .collect();
+        if !eval(form, &assign)? {
+            return Ok(Some(assign));
+        }
+    }
+    Ok(None)
+}
+
+/// Formats an assignment as `a = T, b = F` in the order of `vars`.
+pub fn assignment_to_string(vars: &[String], assign: &HashMap<String, bool>) -> String {
+    let values: Vec<String> = vars.iter()
+        .filter_map(|var| Some(format!("{} = {}", var, if *assign.get(var)? { "T" } else { "F" })))
+        .collect();
+    values.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    #[test]
+    fn truth_table() {
+        let mut parser = Parser::new("ops.txt").unwrap();
+        let form = parser.parse("!(a & b) -> !a | !b").unwrap();
+        assert_eq!(tautology(form.subtree_root(), parser.symdb()), Ok(None));
+        let form = parser.parse("a | b -> a & T").unwrap();
+        let assign = tautology(form.subtree_root(), parser.symdb()).unwrap().unwrap();
+        let vars = variables(form.subtree_root(), parser.symdb());
+        assert_eq!(assignment_to_string(&vars, &assign), "a = F, b = T");
+        let form = parser.parse("a + b").unwrap();
+        assert!(tautology(form.subtree_root(), parser.symdb()).is_err());
+        let vars: Vec<String> = (0..=MAX_VARIABLES).map(|i| format!("p{}", i)).collect();
+        let form = parser.parse(&vars.join(" | ")).unwrap();
+        assert!(tautology(form.subtree_root(), parser.symdb()).unwrap_err().ends_with("use sat"));
+    }
+}
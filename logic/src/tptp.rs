@@ -0,0 +1,492 @@
+//! Translation between TPTP `fof` and `cnf` formulas and ASTs.
+//!
+//! TPTP variables `X`, `Y1` become `x`, `y1`, and lower words keep their names,
+//! which `fol::System` treats as constants rather than variables.
+//! `~ & | => <= <=> <~> ~| ~& = !=` become the connectives of ops.txt, and `![X]:`, `?[X]:` become `x ∀`, `x ∃`.
+//! Function symbols are supported only for the operators in `FUNCTORS`; other applications are rejected,
+//! since the parser has no syntax for them.
+
+use tree::v3::{Tree, Subtree};
+use parser::sym::SymDB;
+use std::collections::{BTreeSet, HashSet};
+
+/// TPTP function symbols and the operators they stand for.
+pub const FUNCTORS: [(&str, &str); 7] = [
+    ("s", "S"), ("uminus", "-"), ("power", "^"), ("times", "*"), ("divide", "/"), ("plus", "+"), ("plus_minus", "+-"),
+];
+
+/// An annotated formula `fof(name, role, formula).`
+pub struct Annotated {
+    pub name: String,
+    pub role: String,
+    pub form: Tree<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Punct(&'static str),
+}
+
+const PUNCTS: [&str; 20] = [
+    "<=>", "<~>", "=>", "<=", "~|", "~&", "!=", "(", ")", "[", "]", ",", ":", ".", "!", "?", "~", "&", "|", "=",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(tokens);
+        }
+        if rest.starts_with('%') {
+            rest = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            let Some((_, after)) = comment.split_once("*/") else {
+                return Err("an unterminated comment".to_string());
+            };
+            rest = after;
+        } else if let Some(quote) = rest.chars().next().filter(|&c| c == '\'' || c == '"') {
+            let Some(end) = rest[1..].find(quote) else {
+                return Err("an unterminated quotation".to_string());
+            };
+            tokens.push(Token::Word(rest[..end + 2].to_string()));
+            rest = &rest[end + 2..];
+        } else if let Some(punct) = PUNCTS.iter().find(|&&punct| rest.starts_with(punct)) {
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        } else {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$')).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("unsupported character: {}", rest.chars().next().unwrap()));
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+    }
+}
+
+fn node(value: &str, children: Vec<Tree<String>>) -> Tree<String> {
+    let mut tree = Tree::new(value.to_string());
+    for child in children {
+        tree.push_tree(tree.root_id(), child);
+    }
+    tree
+}
+
+fn is_tptp_variable(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+struct Reader {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Lower words of the current formula, which variable names must avoid.
+    lower_words: HashSet<String>,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| "unexpected end of input".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}', found {}", punct, self.describe()))
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.peek() {
+            Some(Token::Word(word)) => format!("'{}'", word),
+            Some(Token::Punct(punct)) => format!("'{}'", punct),
+            None => "the end of input".to_string(),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            Token::Punct(punct) => Err(format!("expected a name, found '{}'", punct)),
+        }
+    }
+
+    /// Checks that `word` is a name the parser accepts.
+    fn name(word: &str) -> Result<Tree<String>, String> {
+        if word.starts_with(['\'', '"', '$']) {
+            return Err(format!("unsupported name: {}", word));
+        }
+        Ok(Tree::new(word.to_string()))
+    }
+
+    fn variable(&self, word: &str) -> String {
+        let mut chars = word.chars();
+        let mut name: String = chars.next().unwrap().to_ascii_lowercase().to_string() + chars.as_str();
+        while self.lower_words.contains(&name) {
+            name.push('_');
+        }
+        name
+    }
+
+    /// Skips a balanced sequence of tokens up to the next `,` or `)` at depth 0.
+    fn skip_term(&mut self) -> Result<(), String> {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Some(Token::Punct("(" | "[")) => depth += 1,
+                Some(Token::Punct(")" | "]")) if depth == 0 => return Ok(()),
+                Some(Token::Punct(")" | "]")) => depth -= 1,
+                Some(Token::Punct(",")) if depth == 0 => return Ok(()),
+                None => return Err("unexpected end of input".to_string()),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn annotated(&mut self) -> Result<Annotated, String> {
+        let kind = self.word()?;
+        match kind.as_str() {
+            "fof" | "cnf" => {}
+            "include" => return Err("include is not supported".to_string()),
+            other => return Err(format!("unsupported formula type: {}", other)),
+        }
+        self.expect("(")?;
+        let name = self.word()?;
+        self.expect(",")?;
+        let role = self.word()?;
+        self.expect(",")?;
+        let start = self.pos;
+        self.skip_term()?;
+        self.lower_words = self.tokens[start..self.pos].iter()
+            .filter_map(|token| match token {
+                Token::Word(word) if !is_tptp_variable(word) => Some(word.clone()),
+                _ => None,
+            })
+            .collect();
+        self.pos = start;
+        let form = self.formula().map_err(|err| format!("{}: {}", name, err))?;
+        while self.eat(",") {
+            self.skip_term()?;
+        }
+        self.expect(")")?;
+        self.expect(".")?;
+        // Variables are implicitly universal in `cnf`, and free ones are not allowed in `fof`.
+        let mut free = Vec::new();
+        collect_free(form.subtree_root(), &mut Vec::new(), &mut free, &self.lower_words);
+        let form = free.into_iter().rev().fold(form, |form, var| node("∀", vec![Tree::new(var), form]));
+        Ok(Annotated { name, role, form })
+    }
+
+    fn formula(&mut self) -> Result<Tree<String>, String> {
+        let lhs = self.unit()?;
+        let Some(Token::Punct(punct)) = self.peek().cloned() else {
+            return Ok(lhs);
+        };
+        match punct {
+            "&" | "|" => {
+                let mut form = lhs;
+                while self.eat(punct) {
+                    form = node(punct, vec![form, self.unit()?]);
+                }
+                Ok(form)
+            }
+            "=>" | "<=" | "<=>" | "<~>" | "~|" | "~&" => {
+                self.pos += 1;
+                let rhs = self.unit()?;
+                let imply = |a: &Tree<String>, b: &Tree<String>| node("->", vec![a.clone(), b.clone()]);
+                Ok(match punct {
+                    "=>" => imply(&lhs, &rhs),
+                    "<=" => imply(&rhs, &lhs),
+                    "<=>" => node("&", vec![imply(&lhs, &rhs), imply(&rhs, &lhs)]),
+                    "<~>" => node("!", vec![node("&", vec![imply(&lhs, &rhs), imply(&rhs, &lhs)])]),
+                    "~|" => node("!", vec![node("|", vec![lhs, rhs])]),
+                    _ => node("!", vec![node("&", vec![lhs, rhs])]),
+                })
+            }
+            _ => Ok(lhs),
+        }
+    }
+
+    fn unit(&mut self) -> Result<Tree<String>, String> {
+        if self.eat("~") {
+            return Ok(node("!", vec![self.unit()?]));
+        }
+        if self.eat("(") {
+            let form = self.formula()?;
+            self.expect(")")?;
+            return Ok(form);
+        }
+        for (punct, quantifier) in [("!", "∀"), ("?", "∃")] {
+            if self.eat(punct) {
+                self.expect("[")?;
+                let mut vars = Vec::new();
+                loop {
+                    let var = self.word()?;
+                    if !is_tptp_variable(&var) {
+                        return Err(format!("{} is not a variable", var));
+                    }
+                    vars.push(self.variable(&var));
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect("]")?;
+                self.expect(":")?;
+                let body = self.unit()?;
+                return Ok(vars.into_iter().rev().fold(body, |form, var| node(quantifier, vec![Tree::new(var), form])));
+            }
+        }
+        match self.peek() {
+            Some(Token::Word(word)) if word == "$true" || word == "$false" => {
+                let value = if word == "$true" { "T" } else { "F" };
+                self.pos += 1;
+                return Ok(Tree::new(value.to_string()));
+            }
+            Some(Token::Word(word)) if !is_tptp_variable(word) => {
+                let word = word.clone();
+                let start = self.pos;
+                self.pos += 1;
+                let applied = self.eat("(");
+                if applied {
+                    self.skip_term()?;
+                    while self.eat(",") {
+                        self.skip_term()?;
+                    }
+                    self.expect(")")?;
+                }
+                let is_equation = self.eat("=") || self.eat("!=");
+                self.pos = start;
+                if !is_equation {
+                    if applied {
+                        return Err(format!("predicate application is not supported: {}(...)", word));
+                    }
+                    self.pos += 1;
+                    return Self::name(&word);
+                }
+            }
+            _ => {}
+        }
+        let lhs = self.term()?;
+        for punct in ["=", "!="] {
+            if self.eat(punct) {
+                return Ok(node(punct, vec![lhs, self.term()?]));
+            }
+        }
+        Err(format!("expected '=' or '!=', found {}", self.describe()))
+    }
+
+    fn term(&mut self) -> Result<Tree<String>, String> {
+        let word = self.word()?;
+        if is_tptp_variable(&word) {
+            return Ok(Tree::new(self.variable(&word)));
+        }
+        if !self.eat("(") {
+            return Self::name(&word);
+        }
+        let Some(&(_, op)) = FUNCTORS.iter().find(|(functor, _)| *functor == word) else {
+            return Err(format!("function application is not supported: {}(...)", word));
+        };
+        let mut args = vec![self.term()?];
+        while self.eat(",") {
+            args.push(self.term()?);
+        }
+        self.expect(")")?;
+        Ok(node(op, args))
+    }
+}
+
+/// Collects the variables of `form` that are free and not lower words, in order of appearance.
+fn collect_free(form: Subtree<String>, bound: &mut Vec<String>, free: &mut Vec<String>, lower_words: &HashSet<String>) {
+    let value = form.get_root();
+    if form.is_leaf() {
+        let is_name = value.starts_with(|c: char| c.is_ascii_lowercase()) && !lower_words.contains(value);
+        if is_name && !bound.contains(value) && !free.contains(value) {
+            free.push(value.clone());
+        }
+        return;
+    }
+    let mut args = form.children_root();
+    if value == "∀" || value == "∃" {
+        bound.push(args.next().unwrap().get_root().clone());
+        collect_free(args.next().unwrap(), bound, free, lower_words);
+        bound.pop();
+        return;
+    }
+    for arg in args {
+        collect_free(arg, bound, free, lower_words);
+    }
+}
+
+/// Parses the annotated formulas of a TPTP problem.
+pub fn parse_problem(input: &str) -> Result<Vec<Annotated>, String> {
+    let mut reader = Reader { tokens: tokenize(input)?, pos: 0, lower_words: HashSet::new() };
+    let mut problem = Vec::new();
+    while reader.peek().is_some() {
+        problem.push(reader.annotated()?);
+    }
+    Ok(problem)
+}
+
+fn tptp_variable(name: &str) -> String {
+    let mut chars = name.chars();
+    chars.next().unwrap().to_ascii_uppercase().to_string() + chars.as_str()
+}
+
+fn is_lower_word(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Writer<'a> {
+    symdb: &'a SymDB,
+    /// Names written as lower words rather than as variables.
+    constants: &'a HashSet<String>,
+    /// Names used as terms, which become TPTP variables.
+    terms: BTreeSet<String>,
+    /// Names used as formulas, which become propositional atoms.
+    atoms: BTreeSet<String>,
+}
+
+impl<'a> Writer<'a> {
+    fn formula(&mut self, form: Subtree<String>) -> Result<String, String> {
+        let value = form.get_root().as_str();
+        let args: Vec<Subtree<String>> = form.children_root().collect();
+        Ok(match (value, args.as_slice()) {
+            ("T", []) => "$true".to_string(),
+            ("F", []) => "$false".to_string(),
+            ("!", [arg]) => format!("~ {}", self.formula(*arg)?),
+            ("&" | "|", [lhs, rhs]) => format!("({} {} {})", self.formula(*lhs)?, value, self.formula(*rhs)?),
+            ("->", [lhs, rhs]) => format!("({} => {})", self.formula(*lhs)?, self.formula(*rhs)?),
+            ("=" | "!=", [lhs, rhs]) => format!("({} {} {})", self.term(*lhs)?, value, self.term(*rhs)?),
+            ("∀" | "∃", [var, body]) => {
+                let quantifier = if value == "∀" { "!" } else { "?" };
+                let var = var.get_root();
+                if !is_lower_word(var) {
+                    return Err(format!("{} cannot be a TPTP variable", var));
+                }
+                format!("({}[{}]: {})", quantifier, tptp_variable(var), self.formula(*body)?)
+            }
+            (name, []) if self.symdb.is_variable(name) => {
+                if !is_lower_word(name) {
+                    return Err(format!("{} cannot be a TPTP atom", name));
+                }
+                self.atoms.insert(name.to_string());
+                name.to_string()
+            }
+            _ => return Err(format!("{} is not a TPTP connective", value)),
+        })
+    }
+
+    fn term(&mut self, term: Subtree<String>) -> Result<String, String> {
+        let value = term.get_root().as_str();
+        if term.is_leaf() && self.constants.contains(value) {
+            if !is_lower_word(value) {
+                return Err(format!("{} cannot be a TPTP constant", value));
+            }
+            return Ok(value.to_string());
+        }
+        if term.is_leaf() && self.symdb.is_variable(value) {
+            if !is_lower_word(value) {
+                return Err(format!("{} cannot be a TPTP variable", value));
+            }
+            self.terms.insert(value.to_string());
+            return Ok(tptp_variable(value));
+        }
+        if term.is_leaf() && value.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(value.to_string());
+        }
+        let Some(&(functor, _)) = FUNCTORS.iter().find(|(_, op)| *op == value) else {
+            return Err(format!("{} has no TPTP function symbol", value));
+        };
+        let args: Vec<String> = term.children_root().map(|arg| self.term(arg)).collect::<Result<_, _>>()?;
+        Ok(format!("{}({})", functor, args.join(",")))
+    }
+}
+
+/// Writes `form` as a TPTP `fof` formula whose free variables are universally quantified.
+/// Variables used as terms become TPTP variables, and those used as formulas and `constants` become lower words.
+pub fn formula_to_tptp(form: &Tree<String>, symdb: &SymDB, constants: &HashSet<String>) -> Result<String, String> {
+    let mut writer = Writer { symdb, constants, terms: BTreeSet::new(), atoms: BTreeSet::new() };
+    let body = writer.formula(form.subtree_root())?;
+    if let Some(name) = writer.terms.intersection(&writer.atoms).next() {
+        return Err(format!("{} is used both as a term and as a formula", name));
+    }
+    let free: Vec<String> = crate::fol::free_variables(form.subtree_root(), symdb).into_iter()
+        .filter(|var| writer.terms.contains(var))
+        .collect::<BTreeSet<_>>()
+        .iter()
+        .map(|var| tptp_variable(var))
+        .collect();
+    if free.is_empty() {
+        Ok(body)
+    } else {
+        Ok(format!("![{}]: {}", free.join(","), body))
+    }
+}
+
+/// Writes an annotated formula `fof(name, role, formula).`
+pub fn annotated_to_tptp(name: &str, role: &str, form: &Tree<String>, symdb: &SymDB, constants: &HashSet<String>)
+-> Result<String, String> {
+    Ok(format!("fof({}, {}, {}).", name, role, formula_to_tptp(form, symdb, constants)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parser::Parser;
+
+    #[test]
+    fn import_export() {
+        let mut parser = Parser::new("peano_ops.txt").unwrap();
+        let problem = parse_problem("
+            % comment
+            fof(succ, axiom, ![X, Y]: (s(X) = s(Y) => X = Y)).
+            cnf(c1, negated_conjecture, p | ~ q | X != x, file('p.p', c1)).
+            fof(iff, hypothesis, (p <=> $false)).
+            fof(goal, conjecture, ?[Y]: plus(Y, 0) = Y).
+        ").unwrap();
+        let names: Vec<&str> = problem.iter().map(|annotated| annotated.name.as_str()).collect();
+        assert_eq!(names, ["succ", "c1", "iff", "goal"]);
+        assert_eq!(problem[1].role, "negated_conjecture");
+        for (annotated, expected) in problem.iter().zip([
+            "x ∀ y ∀ S x = S y -> x = y",
+            "x_ ∀ p | ! q | x_ != x",
+            "(p -> F) & (F -> p)",
+            "y ∃ y + 0 = y",
+        ]) {
+            assert_eq!(annotated.form, parser.parse(expected).unwrap());
+        }
+        for (input, err) in [
+            ("fof(f, axiom, f(a) = a).", "function application is not supported: f(...)"),
+            ("fof(f, axiom, ![X]: p(X)).", "predicate application is not supported: p(...)"),
+        ] {
+            assert_eq!(parse_problem(input).err().unwrap(), format!("f: {}", err));
+        }
+        assert!(parse_problem("include('Axioms/SET001-0.ax').").is_err());
+        let form = parser.parse("x ∃ S x = y + 0 -> ! a").unwrap();
+        let exported = annotated_to_tptp("g", "conjecture", &form, parser.symdb(), &HashSet::new()).unwrap();
+        assert_eq!(exported, "fof(g, conjecture, ![Y]: (?[X]: ((s(X) = plus(Y,0)) => ~ a))).");
+        let reimported = parse_problem(&exported).unwrap();
+        assert_eq!(reimported[0].form, parser.parse("y ∀ x ∃ S x = y + 0 -> ! a").unwrap());
+        let mixed = parser.parse("a = b -> a").unwrap();
+        assert!(formula_to_tptp(&mixed, parser.symdb(), &HashSet::new()).is_err());
+    }
+}
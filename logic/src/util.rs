@@ -1,3 +1,8 @@
+use parser::parser::Parser;
+use parser::sym::SymDB;
+use tree::v3::Tree;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 
 pub struct SplitWhitespace<'a> {
     s: &'a str,
@@ -31,3 +36,132 @@ impl<'a> Iterator for SplitWhitespace<'a> {
         Some(next)
     }
 }
+
+/// Parses a substitution `a := f1, b := f2, ...`, or a single `a f1`. Only variables can be substituted.
+/// Commas inside parentheses belong to the formulas.
+pub fn parse_subst(parser: &mut Parser, s: &str) -> Result<Vec<(String, Tree<String>)>, String> {
+    if !s.contains(":=") {
+        let mut args = SplitWhitespace::from(s);
+        let var = args.next().ok_or_else(|| "missing variable".to_string())?;
+        if !parser.symdb().is_variable(var) {
+            return Err(format!("{} is not a variable", var));
+        }
+        return Ok(vec![(var.to_string(), parser.parse(args.remainder())?)]);
+    }
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    let mut subst: Vec<(String, Tree<String>)> = Vec::new();
+    for part in parts {
+        let Some((var, form)) = part.split_once(":=") else {
+            return Err(format!("expected '[variable] := [formula]': {}", part.trim()));
+        };
+        let var = var.trim();
+        if var.is_empty() || var.contains(char::is_whitespace) {
+            return Err(format!("invalid variable: {}", var));
+        }
+        if !parser.symdb().is_variable(var) {
+            return Err(format!("{} is not a variable", var));
+        }
+        if subst.iter().any(|(v, _)| v == var) {
+            return Err(format!("{} is substituted twice", var));
+        }
+        subst.push((var.to_string(), parser.parse(form)?));
+    }
+    Ok(subst)
+}
+
+/// Formats a substitution as `a := f1, b := f2`.
+pub fn subst_to_string(subst: &[(String, Tree<String>)], symdb: &SymDB) -> String {
+    subst.iter()
+        .map(|(var, replace)| format!("{} := {}", var, symdb.ast_to_string_minimal(replace)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Runs the commands in `file_name`, one per line, until `exit`.
+/// Empty lines and lines starting with `#` are skipped. Stops at the first error, prefixed with its line number.
+pub fn run_script(file_name: &str, mut command: impl FnMut(&str) -> Result<(), String>) -> Result<(), String> {
+    let reader = BufReader::new(File::open(file_name).map_err(|err| format!("{}: {}", file_name, err))?);
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "exit" {
+            break;
+        }
+        command(line).map_err(|err| format!("{}:{}: {}: {}", file_name, i + 1, line, err))?;
+    }
+    Ok(())
+}
+
+/// Removes `items[id]` and the later items derived from it, given the `premises` of each item,
+/// and renumbers the premises of the remaining items by `remap`.
+/// Returns the new ID of each item, or `None` if it was removed.
+pub fn retract<T>(items: &mut Vec<T>, id: usize, premises: impl Fn(&T) -> Vec<usize>,
+remap: impl Fn(T, &dyn Fn(usize) -> usize) -> T) -> Vec<Option<usize>> {
+    let mut new_ids: Vec<Option<usize>> = Vec::with_capacity(items.len());
+    let mut next = 0;
+    for (i, item) in items.iter().enumerate() {
+        if i == id || i > id && premises(item).iter().any(|&premise| new_ids[premise].is_none()) {
+            new_ids.push(None);
+        } else {
+            new_ids.push(Some(next));
+            next += 1;
+        }
+    }
+    let old_items = std::mem::take(items);
+    *items = old_items.into_iter().zip(new_ids.iter())
+        .filter(|(_, new_id)| new_id.is_some())
+        .map(|(item, _)| remap(item, &|premise| new_ids[premise].unwrap()))
+        .collect();
+    new_ids
+}
+
+/// Snapshots of a state for `undo` and `redo`.
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self { undo: Vec::new(), redo: Vec::new() }
+    }
+}
+
+impl<T> History<T> {
+    /// Records the state before a change, which discards the states that could be redone.
+    pub fn record(&mut self, before: T) {
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    /// Returns the state before the last change, given the current state.
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let before = self.undo.pop()?;
+        self.redo.push(current);
+        Some(before)
+    }
+
+    /// Returns the state after the last undone change, given the current state.
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let after = self.redo.pop()?;
+        self.undo.push(current);
+        Some(after)
+    }
+}
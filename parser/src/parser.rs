@@ -49,6 +49,63 @@ impl Parser {
         }
     }
 
+    /// Returns a most general unifier of `lhs` and `rhs` whose variables are the variables of `SymDB`.
+    pub fn unify(&self, lhs: Subtree<String>, rhs: Subtree<String>) -> Option<HashMap<String, Tree<String>>> {
+        unify_tree(lhs, rhs, |name| self.symdb().is_variable(name))
+    }
+
+}
+
+/// Replaces the leaves of `ast` with their values in `subst`.
+pub fn substitute_tree(ast: Subtree<String>, subst: &HashMap<String, Tree<String>>) -> Tree<String> {
+    let value = ast.get_root();
+    if let Some(tree) = subst.get(value).filter(|_| ast.is_leaf()) {
+        return tree.clone();
+    }
+    let mut tree = Tree::new(value.clone());
+    for child in ast.children_root() {
+        tree.push_tree(tree.root_id(), substitute_tree(child, subst));
+    }
+    tree
+}
+
+/// Returns a most general unifier of `lhs` and `rhs`, whose variables are the leaves satisfying `is_var`.
+/// The unifier is idempotent: no variable it binds occurs in its values.
+pub fn unify_tree(lhs: Subtree<String>, rhs: Subtree<String>, is_var: impl Fn(&str) -> bool)
+-> Option<HashMap<String, Tree<String>>> {
+    let mut subst: HashMap<String, Tree<String>> = HashMap::new();
+    let mut stack = vec![(lhs.to_owned(), rhs.to_owned())];
+    while let Some((lhs, rhs)) = stack.pop() {
+        let lhs = substitute_tree(lhs.subtree_root(), &subst);
+        let rhs = substitute_tree(rhs.subtree_root(), &subst);
+        if lhs == rhs {
+            continue;
+        }
+        let is_var_leaf = |tree: &Tree<String>| tree.subtree_root().is_leaf() && is_var(tree.get_root().unwrap());
+        let (var, term) = if is_var_leaf(&lhs) {
+            (lhs, rhs)
+        } else if is_var_leaf(&rhs) {
+            (rhs, lhs)
+        } else {
+            if lhs.get_root() != rhs.get_root() || lhs.subtree_root().children_len() != rhs.subtree_root().children_len() {
+                return None;
+            }
+            for (child_lhs, child_rhs) in std::iter::zip(lhs.children_root(), rhs.children_root()) {
+                stack.push((lhs.clone_sub(child_lhs), rhs.clone_sub(child_rhs)));
+            }
+            continue;
+        };
+        let var = var.get_root().unwrap().clone();
+        if term.ids().any(|id| term.get(id).unwrap() == &var) {
+            return None;
+        }
+        let single = HashMap::from([(var.clone(), term.clone())]);
+        for value in subst.values_mut() {
+            *value = substitute_tree(value.subtree_root(), &single);
+        }
+        subst.insert(var, term);
+    }
+    Some(subst)
 }
 
 
@@ -117,4 +174,19 @@ mod tests {
         assert_eq!(map.get("beta"), Some(&pe.parse("12").unwrap()));
         assert_eq!(map.len(), 2);
     }
+
+    #[test]
+    fn unify() {
+        let mut pe = Parser::new("../logic/ops.txt").unwrap();
+        let lhs = pe.parse("a + (b * c) = d").unwrap();
+        let rhs = pe.parse("(x * y) + z = x").unwrap();
+        let subst = pe.unify(lhs.subtree_root(), rhs.subtree_root()).unwrap();
+        let unified = substitute_tree(lhs.subtree_root(), &subst);
+        assert_eq!(unified, substitute_tree(rhs.subtree_root(), &subst));
+        assert_eq!(pe.symdb().ast_to_string_minimal(&unified), "x * y + b * c = x");
+
+        let lhs = pe.parse("a + b").unwrap();
+        let rhs = pe.parse("b + (a * c)").unwrap();
+        assert_eq!(pe.unify(lhs.subtree_root(), rhs.subtree_root()), None);
+    }
 }
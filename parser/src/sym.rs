@@ -2,6 +2,7 @@ use std::str::FromStr;
 use std::{rc::Rc, io::BufRead};
 use std::fs::File;
 use std::io::BufReader;
+use std::collections::HashMap;
 use tree::v3::{Tree, Subtree};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -115,8 +116,9 @@ impl SymDB {
     pub fn len(&self) -> usize {
         self.symbols.len()
     }
+    /// Whether `s` is a variable, that is, neither an operator nor a numeric literal.
     pub fn is_variable(&self, s: &str) -> bool {
-        self.symbols.iter()
+        !s.starts_with(|c: char| c.is_ascii_digit()) && self.symbols.iter()
             .all(|info| info.name != s)
     }
 
@@ -208,6 +210,39 @@ impl SymDB {
         self.ast_prth_to_string(tree_prth.subtree_root())
     }
 
+    // Converts `Tree` with parentheses infomation to LaTeX.
+    fn ast_prth_to_latex(&self, subtree: Subtree<(String, bool)>, templates: &HashMap<String, String>) -> String {
+        let &(ref name, need_prth) = subtree.get_root();
+        let sym_data = self.get(name);
+        let operands: Vec<String> = subtree.children_root()
+            .map(|child| self.ast_prth_to_latex(child, templates))
+            .collect();
+        let s = match templates.get(name) {
+            Some(template) if template.contains('#') => {
+                let mut s = template.clone();
+                for (i, operand) in operands.iter().enumerate().rev() {
+                    s = s.replace(&format!("#{}", i + 1), operand);
+                }
+                s
+            }
+            template => {
+                let name = template.cloned().unwrap_or_else(|| latex_escape(name));
+                let mut tokens: Vec<&str> = operands.iter().map(String::as_str).collect();
+                tokens.insert(sym_data.layout.front.min(tokens.len()), &name);
+                tokens.join(" ")
+            }
+        };
+        if need_prth { format!("({})", s) } else { s }
+    }
+
+    /// Converts `Tree` to LaTeX with as few parentheses as possible.
+    /// An operator with a template is written as the template, where `#1`, `#2`, ... are replaced with the operands;
+    /// a template without `#` replaces only the name of the operator.
+    pub fn ast_to_latex(&self, tree: &Tree<String>, templates: &HashMap<String, String>) -> String {
+        let tree_prth = self.ast_to_ast_prth(&tree.subtree_root());
+        self.ast_prth_to_latex(tree_prth.subtree_root(), templates)
+    }
+
 }
 
 pub struct IterNames<'a> {
@@ -243,6 +278,24 @@ impl<'a> Iterator for Iter<'a> {
     }
 }
 
+/// Escapes the characters that have a special meaning in LaTeX math mode.
+pub fn latex_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped += r"\sim{}",
+            '^' => escaped += r"\hat{}",
+            '\\' => escaped += r"\backslash{}",
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;